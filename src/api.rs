@@ -0,0 +1,190 @@
+//! The JSON API, written to `api/v1` next to the HTML pages. Fields are only
+//! ever added within a version, timestamps are ISO 8601 in UTC like
+//! `2022-03-01T10:00:00Z`.
+//!
+//! - `status.json`: `generated_at`, the overall `status` (the most severe
+//!   component status), and the number of `open_incidents` and
+//!   `upcoming_maintenance`.
+//! - `components.json`: `generated_at` and the `components`, in the order of
+//!   the status page.
+//! - `incidents.json`: `generated_at` and all `incidents` without their
+//!   updates: open ones first, then upcoming maintenance, then the history.
+//! - `incidents/<id>.json`: a single incident including its `updates`.
+//!
+//! A status has a `key`, `name`, `color` and `rank`, a higher rank is more
+//! severe. A component has an `id` (its slug), `name`, `description` and `url`
+//! (both may be `null`) and `status`. Hidden components are left out.
+//!
+//! An incident has an `id`, `title`, `url` (its HTML page relative to the
+//! status page), `status` (`open`, `scheduled` or `closed`), `phase`
+//! (`investigating`, `identified`, `monitoring` or `resolved`), `severity` (a
+//! status or `null`), the ids of the affected `components`, `opened`,
+//! `closed` (or `null`), `updated` and `maintenance` (`start` and `end`, or
+//! `null`). Updates have a `datetime`, `phase` and `html`, newest first.
+
+use chrono::prelude::*;
+use serde::Serialize;
+use std::fs::create_dir_all;
+use std::path::Path;
+
+use crate::types::{
+    overall_status, ComponentStatus, Components, Error, Incident, IncidentStatus, IncidentUpdate,
+};
+use crate::writer::write_json;
+
+#[derive(Debug, Serialize)]
+struct ApiStatus<'a> {
+    generated_at: DateTime<Utc>,
+    status: &'a ComponentStatus,
+    open_incidents: usize,
+    upcoming_maintenance: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct ApiComponent<'a> {
+    id: &'a str,
+    name: &'a str,
+    description: Option<&'a str>,
+    url: Option<&'a str>,
+    status: &'a ComponentStatus,
+}
+
+#[derive(Debug, Serialize)]
+struct ApiComponents<'a> {
+    generated_at: DateTime<Utc>,
+    components: Vec<ApiComponent<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct ApiMaintenanceWindow {
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+struct ApiUpdate<'a> {
+    datetime: DateTime<Utc>,
+    phase: &'static str,
+    html: &'a str,
+}
+
+impl<'a> From<&'a IncidentUpdate> for ApiUpdate<'a> {
+    fn from(update: &'a IncidentUpdate) -> Self {
+        ApiUpdate {
+            datetime: update.datetime,
+            phase: update.phase.key(),
+            html: &update.text,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct ApiIncident<'a> {
+    id: &'a str,
+    title: &'a str,
+    url: String,
+    status: &'static str,
+    phase: &'static str,
+    severity: Option<&'a ComponentStatus>,
+    components: &'a [String],
+    opened: DateTime<Utc>,
+    closed: Option<DateTime<Utc>>,
+    updated: DateTime<Utc>,
+    maintenance: Option<ApiMaintenanceWindow>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    updates: Option<Vec<ApiUpdate<'a>>>,
+}
+
+impl<'a> ApiIncident<'a> {
+    fn new(incident: &'a Incident, with_updates: bool) -> Self {
+        ApiIncident {
+            id: &incident.id,
+            title: &incident.title,
+            url: format!("{}.html", incident.id),
+            status: incident.status.key(),
+            phase: incident.phase.key(),
+            severity: incident.severity.as_ref(),
+            components: &incident.component_names,
+            opened: incident.opened,
+            closed: incident.closed,
+            updated: incident.updated(),
+            maintenance: incident
+                .maintenance
+                .as_ref()
+                .map(|window| ApiMaintenanceWindow {
+                    start: window.start,
+                    end: window.end,
+                }),
+            updates: with_updates.then(|| incident.updates.iter().map(ApiUpdate::from).collect()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct ApiIncidents<'a> {
+    generated_at: DateTime<Utc>,
+    incidents: Vec<ApiIncident<'a>>,
+}
+
+/// Writes the API files to `output_dir/api/v1`. `default_status` is the
+/// overall status if there are no components.
+pub fn write_api(
+    components: &Components,
+    incidents: &[&Incident],
+    default_status: &ComponentStatus,
+    now: DateTime<Utc>,
+    output_dir: &Path,
+) -> Result<(), Error> {
+    let now = now.trunc_subsecs(0);
+    let api_dir = output_dir.join("api").join("v1");
+    let incidents_dir = api_dir.join("incidents");
+    create_dir_all(&incidents_dir).map_err(|e| Error::io(&incidents_dir, e))?;
+
+    let count = |status| {
+        incidents
+            .iter()
+            .filter(|incident| incident.status == status)
+            .count()
+    };
+    let status = ApiStatus {
+        generated_at: now,
+        status: overall_status(components, default_status),
+        open_incidents: count(IncidentStatus::Open),
+        upcoming_maintenance: count(IncidentStatus::Scheduled),
+    };
+    write_json(&status, api_dir.join("status.json"))?;
+
+    let api_components: Vec<ApiComponent> = components
+        .iter()
+        .filter(|(_, component)| !component.hidden)
+        .map(|(slug, component)| ApiComponent {
+            id: slug,
+            name: &component.name,
+            description: component.description.as_deref(),
+            url: component.url.as_deref(),
+            status: &component.status,
+        })
+        .collect();
+    let api_components = ApiComponents {
+        generated_at: now,
+        components: api_components,
+    };
+    write_json(&api_components, api_dir.join("components.json"))?;
+
+    let api_incidents = ApiIncidents {
+        generated_at: now,
+        incidents: incidents
+            .iter()
+            .map(|incident| ApiIncident::new(incident, false))
+            .collect(),
+    };
+    write_json(&api_incidents, api_dir.join("incidents.json"))?;
+
+    for incident in incidents.iter() {
+        write_json(
+            &ApiIncident::new(incident, true),
+            incidents_dir.join(format!("{}.json", incident.id)),
+        )?;
+    }
+    Ok(())
+}
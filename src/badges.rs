@@ -0,0 +1,128 @@
+//! Status badges in the style of shields.io, `badges/overall.svg` and one
+//! `badges/<slug>.svg` per component. Optionally each badge also gets a JSON
+//! file in the shields.io endpoint schema, so shields.io can render it in
+//! any of its styles:
+//! `https://img.shields.io/endpoint?url=https://status.example.com/badges/overall.json`
+
+use serde::Serialize;
+use std::fs::{create_dir_all, write};
+use std::path::Path;
+
+use crate::types::{ComponentStatus, Components, Error};
+use crate::utils::progress;
+use crate::writer::write_json;
+
+const LABEL_COLOR: &str = "#555";
+/// The name of the overall badge, components can't use it as their slug.
+pub const OVERALL: &str = "overall";
+
+/// The shields.io endpoint schema, see <https://shields.io/badges/endpoint-badge>.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ShieldsEndpoint<'a> {
+    schema_version: u8,
+    label: &'a str,
+    message: &'a str,
+    color: &'a str,
+}
+
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Roughly the width of `text` in 11px Verdana, plus padding. Exact widths
+/// would need the font metrics, this is close enough for a badge.
+fn text_width(text: &str) -> u32 {
+    let width: f64 = text
+        .chars()
+        .map(|c| match c {
+            'i' | 'j' | 'l' | 'I' | '.' | ',' | ':' | ';' | '!' | '|' | '\'' => 3.5,
+            'f' | 'r' | 't' | ' ' | '(' | ')' | '-' => 5.0,
+            'm' | 'w' | 'M' | 'W' => 10.0,
+            c if c.is_uppercase() => 7.5,
+            _ => 7.0,
+        })
+        .sum();
+    width.ceil() as u32 + 10
+}
+
+fn render_svg(label: &str, message: &str, color: &str) -> String {
+    let label_width = text_width(label);
+    let message_width = text_width(message);
+    let width = label_width + message_width;
+    let label_x = label_width as f64 / 2.0;
+    let message_x = label_width as f64 + message_width as f64 / 2.0;
+    let label = escape_xml(label);
+    let message = escape_xml(message);
+    format!(
+        r##"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="20" role="img" aria-label="{label}: {message}">
+<title>{label}: {message}</title>
+<linearGradient id="s" x2="0" y2="100%"><stop offset="0" stop-color="#bbb" stop-opacity=".1"/><stop offset="1" stop-opacity=".1"/></linearGradient>
+<clipPath id="r"><rect width="{width}" height="20" rx="3" fill="#fff"/></clipPath>
+<g clip-path="url(#r)"><rect width="{label_width}" height="20" fill="{LABEL_COLOR}"/><rect x="{label_width}" width="{message_width}" height="20" fill="{color}"/><rect width="{width}" height="20" fill="url(#s)"/></g>
+<g fill="#fff" text-anchor="middle" font-family="Verdana,Geneva,DejaVu Sans,sans-serif" font-size="11">
+<text x="{label_x}" y="15" fill="#010101" fill-opacity=".3">{label}</text><text x="{label_x}" y="14">{label}</text>
+<text x="{message_x}" y="15" fill="#010101" fill-opacity=".3">{message}</text><text x="{message_x}" y="14">{message}</text>
+</g>
+</svg>
+"##
+    )
+}
+
+/// Writes `<name>.svg` and, with `shields`, `<name>.json` to `directory`.
+fn write_badge(
+    directory: &Path,
+    name: &str,
+    label: &str,
+    status: &ComponentStatus,
+    shields: bool,
+) -> Result<(), Error> {
+    let path = directory.join(format!("{}.svg", name));
+    progress!("Writing file {} ...", path.display());
+    write(&path, render_svg(label, &status.name, &status.color))
+        .map_err(|e| Error::io(&path, e))?;
+    if shields {
+        let endpoint = ShieldsEndpoint {
+            schema_version: 1,
+            label,
+            message: &status.name,
+            color: status.color.trim_start_matches('#'),
+        };
+        write_json(&endpoint, directory.join(format!("{}.json", name)))?;
+    }
+    Ok(())
+}
+
+/// Writes the overall badge and the badge of each public component to
+/// `output_dir/badges`.
+pub fn write_badges(
+    components: &Components,
+    overall: &ComponentStatus,
+    shields: bool,
+    output_dir: &Path,
+) -> Result<(), Error> {
+    let badges_dir = output_dir.join("badges");
+    create_dir_all(&badges_dir).map_err(|e| Error::io(&badges_dir, e))?;
+    write_badge(&badges_dir, OVERALL, "status", overall, shields)?;
+    for (slug, component) in components.iter().filter(|(_, c)| !c.hidden) {
+        write_badge(
+            &badges_dir,
+            slug,
+            &component.name,
+            &component.status,
+            shields,
+        )?;
+    }
+    Ok(())
+}
@@ -0,0 +1,107 @@
+use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
+
+/// Exit codes, one per class of [`crate::types::Error`].
+const EXIT_CODES: &str = "\
+Exit codes:
+  0  Success
+  2  Configuration error, including a missing or unreadable configuration file
+  3  Network error
+  4  API error reported by the backend
+  5  Parse error in fetched data or incident files
+  6  Template error
+  7  I/O error, e.g. while reading incident files or writing the output";
+
+/// Generates a static status page from incidents tracked as issues.
+#[derive(Debug, Parser)]
+#[command(version, about, after_help = EXIT_CODES)]
+pub struct Cli {
+    /// Path to the configuration file. Relative paths in the configuration are
+    /// resolved against the directory containing it.
+    #[arg(short, long, global = true, default_value = "config.toml")]
+    pub config: PathBuf,
+
+    /// Write the generated files to this directory instead of the configured
+    /// output_directory.
+    #[arg(short, long, global = true)]
+    pub output: Option<PathBuf>,
+
+    /// Only print warnings and errors.
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// Print additional details such as the API rate limit budget.
+    #[arg(short, long, global = true)]
+    pub verbose: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Fetch all incidents and generate the status page (default).
+    Build,
+    /// Validate the configuration and the labels in the backend.
+    Check,
+    /// Create missing labels and clean up obsolete component labels.
+    SyncLabels(SyncLabelsArgs),
+    /// Build the status page to a temporary directory and serve it over HTTP,
+    /// rebuilding and reloading it in the browser whenever templates, static
+    /// files or the configuration change.
+    Serve(ServeArgs),
+    /// Build the status page and rebuild it whenever templates, static files
+    /// or the configuration change.
+    Watch,
+    /// Create a configuration file and default templates.
+    Init(InitArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct SyncLabelsArgs {
+    /// Only show the planned changes.
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Delete component labels that don't belong to a configured component.
+    #[arg(long)]
+    pub delete_obsolete: bool,
+
+    /// Rename an obsolete label instead of creating the new one, e.g.
+    /// `--rename component/db=component/database`. Renames of labels that
+    /// aren't obsolete, or to labels that aren't missing, are skipped with a
+    /// warning.
+    #[arg(long, value_name = "OLD=NEW", value_parser = parse_rename)]
+    pub rename: Vec<(String, String)>,
+}
+
+#[derive(Debug, Args)]
+pub struct ServeArgs {
+    /// Address to listen on.
+    #[arg(long, default_value = "127.0.0.1")]
+    pub bind: String,
+
+    /// Port to listen on.
+    #[arg(short, long, default_value_t = 8000)]
+    pub port: u16,
+}
+
+#[derive(Debug, Args)]
+pub struct InitArgs {
+    /// Directory to initialize.
+    #[arg(default_value = ".")]
+    pub directory: PathBuf,
+
+    /// Overwrite existing files.
+    #[arg(long)]
+    pub force: bool,
+}
+
+fn parse_rename(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((from, to)) if !from.is_empty() && !to.is_empty() => {
+            Ok((String::from(from), String::from(to)))
+        }
+        _ => Err(String::from("expected OLD=NEW")),
+    }
+}
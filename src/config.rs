@@ -1,88 +1,646 @@
-use serde::Deserialize;
-use serde::Deserializer;
-use std::env::current_dir;
-use std::path::PathBuf;
+use serde::de::value::MapAccessDeserializer;
+use serde::de::{self, MapAccess, Visitor};
+use serde::{Deserialize, Deserializer};
+use slug::slugify;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::badges;
+use crate::types::{ComponentStatus, Error};
+
+/// The key of the status level components get during an active maintenance
+/// window.
+pub const MAINTENANCE_STATUS: &str = "under-maintenance";
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum Backend {
-    GITHUB,
-    // GITLAB,
+    GitHub,
+    GitLab,
+    #[serde(alias = "gitea")]
+    Forgejo,
+    Files,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct GitHub {
     pub owner: String,
     pub repository: String,
+    #[serde(default = "default_github_graphql_url")]
+    pub graphql_url: String,
+    #[serde(default = "default_github_rest_url")]
+    pub rest_url: String,
+    /// Maximum number of closed incidents to fetch, newest first.
+    pub history_limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GitLab {
+    #[serde(default = "default_gitlab_url")]
+    pub url: String,
+    pub project: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Forgejo {
+    pub url: String,
+    pub owner: String,
+    pub repository: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Files {
+    pub directory: PathBuf,
+}
+
+/// Color and description used when creating a label in the backend.
+#[derive(Debug, Default, Deserialize)]
+pub struct LabelStyle {
+    pub color: Option<String>,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct LabelsConfig {
+    /// Component labels are this prefix followed by the component slug.
+    pub component_prefix: String,
+    /// Status labels are this prefix followed by the status key, unless the
+    /// status sets its own label.
+    pub status_prefix: String,
+    /// The label marking scheduled maintenance.
+    pub maintenance: String,
+    /// Phase labels are this prefix followed by the phase, e.g. `identified`.
+    /// Only the current label counts, it sets the phase of the latest update.
+    pub phase_prefix: String,
+    /// Defaults for all component labels. `{component}` in the description
+    /// is replaced with the component name.
+    pub component: LabelStyle,
+    /// Per component overrides, keyed by the component slug.
+    pub components: HashMap<String, LabelStyle>,
+    /// Per status label settings, keyed by the full label name.
+    pub statuses: HashMap<String, LabelStyle>,
+}
+
+impl Default for LabelsConfig {
+    fn default() -> Self {
+        LabelsConfig {
+            component_prefix: String::from("component/"),
+            status_prefix: String::from("status::"),
+            maintenance: String::from("maintenance"),
+            phase_prefix: String::from("phase::"),
+            component: LabelStyle::default(),
+            components: HashMap::new(),
+            statuses: HashMap::new(),
+        }
+    }
+}
+
+/// Selects which comments of an incident are published as updates. The
+/// description of the issue is always published.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct UpdateFilter {
+    /// Only comments by these users are published.
+    pub authors: Vec<String>,
+    /// Only comments starting with this marker are published, without it.
+    pub marker: Option<String>,
+    /// Comments containing this marker, e.g. `<!-- internal -->`, are not
+    /// published.
+    pub hidden_marker: Option<String>,
+    /// Comments with any of these reactions, e.g. `-1` or `eyes`, are not
+    /// published.
+    pub exclude_reactions: Vec<String>,
+}
+
+/// Selects which issues are incidents, everything else in the repository is
+/// ignored.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct IncidentFilter {
+    /// Only issues with this label are incidents.
+    pub label: Option<String>,
+    /// Issues with any of these labels are not incidents.
+    pub exclude_labels: Vec<String>,
+    /// Only issues opened by these users are incidents.
+    pub authors: Vec<String>,
+    /// A GitHub search query like `label:incident -label:internal`, combined
+    /// with the repository and issue state. Only supported by the github
+    /// backend.
+    pub query: Option<String>,
+    /// Which comments of the selected issues are published.
+    pub updates: UpdateFilter,
+}
+
+/// A component, configured either by its name alone or as a table.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ComponentConfig {
+    pub name: String,
+    /// Shown below the name on the status page.
+    pub description: Option<String>,
+    /// Sorts the components, lower values first.
+    pub order: Option<i64>,
+    /// A link to the documentation of the component.
+    pub url: Option<String>,
+    /// Leaves the component out of the component list, the affected
+    /// components of incidents, the badges, feeds and APIs, and the overall
+    /// status. Its label still works.
+    #[serde(default)]
+    pub hidden: bool,
+    /// Replaces the slug derived from the name, which is also used in the
+    /// component label and file names. Only lowercase letters, digits, `-` and
+    /// `_` are allowed.
+    pub slug: Option<String>,
+    /// The key of the status level without incidents, instead of the lowest
+    /// level.
+    pub status: Option<String>,
+}
+
+impl ComponentConfig {
+    pub fn slug(&self) -> String {
+        match &self.slug {
+            Some(slug) => slug.clone(),
+            None => slugify(&self.name),
+        }
+    }
+}
+
+/// A component name or table. Unlike an untagged enum, this passes on the
+/// errors of the table, e.g. about a misspelled field.
+struct ComponentEntry(ComponentConfig);
+
+impl<'de> Deserialize<'de> for ComponentEntry {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct EntryVisitor;
+
+        impl<'de> Visitor<'de> for EntryVisitor {
+            type Value = ComponentEntry;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a component name or a table with a name")
+            }
+
+            fn visit_str<E: de::Error>(self, name: &str) -> Result<Self::Value, E> {
+                Ok(ComponentEntry(ComponentConfig {
+                    name: String::from(name),
+                    ..Default::default()
+                }))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                ComponentConfig::deserialize(MapAccessDeserializer::new(map)).map(ComponentEntry)
+            }
+        }
+
+        deserializer.deserialize_any(EntryVisitor)
+    }
+}
+
+fn deserialize_components<'de, D>(deserializer: D) -> Result<Vec<ComponentConfig>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let entries = Vec::<ComponentEntry>::deserialize(deserializer)?;
+    Ok(entries.into_iter().map(|entry| entry.0).collect())
+}
+
+/// Components shown together under a common name. The status of the group is
+/// the most severe status of its components.
+#[derive(Debug, Deserialize)]
+pub struct ComponentGroupConfig {
+    pub name: String,
+    #[serde(deserialize_with = "deserialize_components")]
+    pub components: Vec<ComponentConfig>,
+    /// Whether the group starts out collapsed on the status page.
+    #[serde(default)]
+    pub collapsed: bool,
+}
+
+/// Settings of the SVG status badges.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct BadgesConfig {
+    /// Whether `badges/overall.svg` and the badges of each component are
+    /// written.
+    pub enabled: bool,
+    /// Also write a shields.io endpoint JSON file next to each badge.
+    pub shields: bool,
+}
+
+impl Default for BadgesConfig {
+    fn default() -> Self {
+        BadgesConfig {
+            enabled: true,
+            shields: false,
+        }
+    }
+}
+
+/// Settings of the incident statistics.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct StatsConfig {
+    /// Whether `stats.html` and its JSON and CSV exports are written.
+    pub enabled: bool,
+}
+
+impl Default for StatsConfig {
+    fn default() -> Self {
+        StatsConfig { enabled: true }
+    }
+}
+
+/// Settings of the Atom and RSS feeds.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct FeedsConfig {
+    /// Whether `feed.atom`, `feed.rss` and the feeds of each component in
+    /// `feeds/` are written.
+    pub enabled: bool,
+    /// One entry per update instead of one per incident.
+    pub updates: bool,
+}
+
+impl Default for FeedsConfig {
+    fn default() -> Self {
+        FeedsConfig {
+            enabled: true,
+            updates: false,
+        }
+    }
+}
+
+/// The component statuses of the Statuspage.io API.
+pub const STATUSPAGE_STATUSES: &[&str] = &[
+    "operational",
+    "under_maintenance",
+    "degraded_performance",
+    "partial_outage",
+    "major_outage",
+];
+
+/// Settings of the Statuspage.io compatible files in `api/v2`.
+#[derive(Debug, Deserialize)]
+pub struct Statuspage {
+    #[serde(default = "default_statuspage_id")]
+    pub page_id: String,
+    #[serde(default = "default_statuspage_name")]
+    pub name: String,
+    /// Maps status keys to one of [`STATUSPAGE_STATUSES`], for levels whose
+    /// key (with `_` instead of `-`) isn't one already.
+    #[serde(default)]
+    pub statuses: HashMap<String, String>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct Config {
     pub backend: Backend,
-    pub components: Vec<String>,
+    #[serde(default)]
+    pub badges: BadgesConfig,
+    /// The URL the status page is published at. Feeds need it for absolute
+    /// links, they fall back to relative ones.
+    pub base_url: Option<String>,
+    /// Components that don't belong to a group.
+    #[serde(default, deserialize_with = "deserialize_components")]
+    pub components: Vec<ComponentConfig>,
+    #[serde(default)]
+    pub component_groups: Vec<ComponentGroupConfig>,
+    #[serde(default)]
+    pub feeds: FeedsConfig,
     pub github: Option<GitHub>,
+    pub gitlab: Option<GitLab>,
+    pub forgejo: Option<Forgejo>,
+    pub files: Option<Files>,
+    #[serde(default)]
+    pub incidents: IncidentFilter,
+    #[serde(default)]
+    pub labels: LabelsConfig,
     #[serde(
         rename(deserialize = "output_directory"),
-        deserialize_with = "deserialize_dir",
         default = "default_output_dir"
     )]
     pub output_dir: PathBuf,
-    #[serde(
-        rename(deserialize = "static_directory"),
-        deserialize_with = "deserialize_dir"
-    )]
+    #[serde(rename(deserialize = "static_directory"))]
     pub static_dir: PathBuf,
+    #[serde(default)]
+    pub stats: StatsConfig,
+    pub statuspage: Option<Statuspage>,
+    /// The status levels, in any order. The lowest ranked level is the status
+    /// of components without incidents.
+    #[serde(default = "default_statuses")]
+    pub statuses: Vec<ComponentStatus>,
     #[serde(
         rename(deserialize = "template_directory"),
-        deserialize_with = "deserialize_template_dir",
         default = "default_template_dir"
     )]
     pub template_dir: PathBuf,
 }
 
+fn default_github_graphql_url() -> String {
+    String::from("https://api.github.com/graphql")
+}
+
+fn default_github_rest_url() -> String {
+    String::from("https://api.github.com")
+}
+
+fn default_gitlab_url() -> String {
+    String::from("https://gitlab.com")
+}
+
+fn default_statuspage_id() -> String {
+    String::from("statuspagers")
+}
+
+fn default_statuspage_name() -> String {
+    String::from("Statuspagers")
+}
+
 fn default_output_dir() -> PathBuf {
-    let buf = current_dir().unwrap().join("html");
-    if !buf.exists() {
-        eprintln!("Path {:?} does not exist.", buf);
+    PathBuf::from("html")
+}
+
+fn default_template_dir() -> PathBuf {
+    PathBuf::from("templates")
+}
+
+fn default_statuses() -> Vec<ComponentStatus> {
+    let status = |key: &str, name: &str, color: &str, rank| ComponentStatus {
+        key: String::from(key),
+        name: String::from(name),
+        color: String::from(color),
+        rank,
+        label: None,
+    };
+    vec![
+        status("operational", "Operational", "#22c55e", 0),
+        status(MAINTENANCE_STATUS, "Under Maintenance", "#3b82f6", 1),
+        status("degraded-performance", "Degraded Performance", "#eab308", 2),
+        status("partial-outage", "Partial Outage", "#f97316", 3),
+        status("major-outage", "Major Outage", "#ef4444", 4),
+    ]
+}
+
+fn is_hex_color(color: &str) -> bool {
+    match color.strip_prefix('#') {
+        Some(hex) => matches!(hex.len(), 3 | 6) && hex.chars().all(|c| c.is_ascii_hexdigit()),
+        None => false,
     }
-    if !buf.is_dir() {
-        eprintln!("Path {:?} is not a directory.", buf);
+}
+
+/// Makes sure labels can be told apart by their prefix.
+fn check_labels(labels: &LabelsConfig) -> Result<(), Error> {
+    let prefixes = [
+        ("component_prefix", &labels.component_prefix),
+        ("phase_prefix", &labels.phase_prefix),
+        ("status_prefix", &labels.status_prefix),
+    ];
+    for (i, (name, prefix)) in prefixes.iter().enumerate() {
+        if prefix.is_empty() {
+            return Err(Error::Config(format!("The {} must not be empty.", name)));
+        }
+        for (other_name, other) in prefixes[i + 1..].iter() {
+            if prefix.starts_with(other.as_str()) || other.starts_with(prefix.as_str()) {
+                return Err(Error::Config(format!(
+                    "The {} {:?} and {} {:?} overlap.",
+                    name, prefix, other_name, other
+                )));
+            }
+        }
     }
-    buf.canonicalize().unwrap()
+    Ok(())
 }
 
-fn deserialize_dir<'de, D>(deserializer: D) -> Result<PathBuf, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    let mut buf = PathBuf::deserialize(deserializer)?;
-    if buf.is_relative() {
-        buf = current_dir().unwrap().join(buf);
+/// Makes sure every component and group has its own slug, so their labels
+/// can be told apart, that configured slugs are safe to use in file names and
+/// don't replace the overall badge, and that default statuses exist.
+fn check_components(config: &Config) -> Result<(), Error> {
+    let mut slugs = HashSet::new();
+    let components = config.components.iter().chain(
+        config
+            .component_groups
+            .iter()
+            .flat_map(|g| g.components.iter()),
+    );
+    let entries = components
+        .clone()
+        .map(|component| (&component.name, component.slug()))
+        .chain(
+            config
+                .component_groups
+                .iter()
+                .map(|group| (&group.name, slugify(&group.name))),
+        );
+    for (name, slug) in entries {
+        if slug.is_empty() {
+            return Err(Error::Config(format!(
+                "The component or group {:?} has an empty slug.",
+                name
+            )));
+        }
+        if !slug
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+        {
+            return Err(Error::Config(format!(
+                "The slug {:?} of component {:?} may only contain lowercase letters, digits, - and _.",
+                slug, name
+            )));
+        }
+        if slug == badges::OVERALL {
+            return Err(Error::Config(format!(
+                "The component or group {:?} can't use the slug {:?}, it is reserved for the overall badge.",
+                name, slug
+            )));
+        }
+        if !slugs.insert(slug) {
+            return Err(Error::Config(format!(
+                "The component or group {:?} is defined more than once.",
+                name
+            )));
+        }
     }
-    if !buf.exists() {
-        eprintln!("Path {:?} does not exist.", buf);
+    for group in config.component_groups.iter() {
+        if group.components.is_empty() {
+            return Err(Error::Config(format!(
+                "The component group {:?} has no components.",
+                group.name
+            )));
+        }
     }
-    if !buf.is_dir() {
-        eprintln!("Path {:?} is not a directory.", buf);
+    for component in components {
+        if let Some(status) = &component.status {
+            if !config.statuses.iter().any(|level| &level.key == status) {
+                return Err(Error::Config(format!(
+                    "The component {:?} has the unknown status {:?}.",
+                    component.name, status
+                )));
+            }
+        }
     }
-    Ok(buf.canonicalize().unwrap())
+    Ok(())
 }
 
-fn default_template_dir() -> PathBuf {
-    let buf = current_dir().unwrap().join("templates");
-    if !buf.exists() {
-        eprintln!("Path {:?} does not exist.", buf);
+/// Makes sure the status levels can be told apart and used in CSS.
+fn check_statuses(statuses: &[ComponentStatus]) -> Result<(), Error> {
+    if statuses.is_empty() {
+        return Err(Error::Config(String::from(
+            "At least one status level is required.",
+        )));
     }
-    if !buf.is_dir() {
-        eprintln!("Path {:?} is not a directory.", buf);
+    let mut keys = HashSet::new();
+    for status in statuses.iter() {
+        if status.key.is_empty()
+            || !status
+                .key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(Error::Config(format!(
+                "The status key {:?} may only contain letters, digits, - and _.",
+                status.key
+            )));
+        }
+        if !keys.insert(status.key.as_str()) {
+            return Err(Error::Config(format!(
+                "The status {:?} is defined more than once.",
+                status.key
+            )));
+        }
+        if !is_hex_color(&status.color) {
+            return Err(Error::Config(format!(
+                "The color {:?} of status {:?} is not a hex color like \"#22c55e\".",
+                status.color, status.key
+            )));
+        }
     }
+    Ok(())
+}
 
-    buf.canonicalize().unwrap().join("**")
+fn check_statuspage(statuspage: &Statuspage, statuses: &[ComponentStatus]) -> Result<(), Error> {
+    for (key, value) in statuspage.statuses.iter() {
+        if !statuses.iter().any(|status| &status.key == key) {
+            return Err(Error::Config(format!(
+                "The statuspage status {:?} is not a configured status.",
+                key
+            )));
+        }
+        if !STATUSPAGE_STATUSES.contains(&value.as_str()) {
+            return Err(Error::Config(format!(
+                "The statuspage status {:?} of {:?} must be one of {}.",
+                value,
+                key,
+                STATUSPAGE_STATUSES.join(", ")
+            )));
+        }
+    }
+    Ok(())
 }
 
-fn deserialize_template_dir<'de, D>(deserializer: D) -> Result<PathBuf, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    Ok(deserialize_dir(deserializer).unwrap().join("**"))
+/// Resolves `path` to its canonical form and makes sure it is a directory.
+fn check_dir(name: &str, path: &Path) -> Result<PathBuf, Error> {
+    if !path.is_dir() {
+        return Err(Error::Config(format!(
+            "The {} {:?} does not exist or is not a directory.",
+            name, path
+        )));
+    }
+    path.canonicalize()
+        .map_err(|e| Error::Config(format!("The {} {:?}: {}", name, path, e)))
+}
+
+impl Config {
+    /// Reads the configuration from `path`. Relative paths in it are resolved
+    /// against the directory containing the file. The output directory does
+    /// not need to exist yet. A missing or unreadable file is a configuration
+    /// error, too.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let config_string = fs::read_to_string(path)
+            .map_err(|e| Error::Config(format!("Cannot read {}: {}", path.display(), e)))?;
+        let mut config: Config = toml::from_str(&config_string)?;
+        check_labels(&config.labels)?;
+        check_statuses(&config.statuses)?;
+        check_components(&config)?;
+        if let Some(statuspage) = &config.statuspage {
+            check_statuspage(statuspage, &config.statuses)?;
+        }
+        if let Some(base_url) = config.base_url.as_mut() {
+            if !base_url.ends_with('/') {
+                base_url.push('/');
+            }
+        }
+        let base = path.parent().unwrap_or_else(|| Path::new(""));
+        config.output_dir = base.join(&config.output_dir);
+        config.static_dir = check_dir("static_directory", &base.join(&config.static_dir))?;
+        config.template_dir = check_dir("template_directory", &base.join(&config.template_dir))?;
+        if let Some(files) = config.files.as_mut() {
+            files.directory = check_dir("files directory", &base.join(&files.directory))?;
+        }
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::config;
+
+    #[test]
+    fn rejects_unsafe_slugs() {
+        for slug in ["../../escape", "a/b", "Tools"] {
+            let config = config(&format!(
+                "components = [{{ name = \"Tools\", slug = {:?} }}]",
+                slug
+            ));
+            match check_components(&config) {
+                Err(Error::Config(msg)) => assert!(msg.contains("may only contain"), "{}", msg),
+                other => panic!("expected a configuration error, got {:?}", other),
+            }
+        }
+        let config = config("components = [{ name = \"Internal Tools\", slug = \"tools_2\" }]");
+        assert!(check_components(&config).is_ok());
+    }
+
+    #[test]
+    fn reports_component_field_errors() {
+        for (components, error) in [
+            (
+                "[{ name = \"Internal\", hiden = true }]",
+                "unknown field `hiden`",
+            ),
+            (
+                "[{ name = \"Internal\", hidden = \"yes\" }]",
+                "invalid type",
+            ),
+            ("[42]", "a component name or a table"),
+        ] {
+            let result = toml::from_str::<Config>(&format!(
+                "backend = \"files\"\ncomponents = {}",
+                components
+            ));
+            match result {
+                Err(e) => assert!(e.to_string().contains(error), "{}", e),
+                Ok(_) => panic!("expected an error for {}", components),
+            }
+        }
+    }
+
+    #[test]
+    fn reserves_overall_slug() {
+        for components in [
+            "[\"Overall\"]",
+            "[{ name = \"Everything\", slug = \"overall\" }]",
+        ] {
+            let config = config(&format!("components = {}", components));
+            match check_components(&config) {
+                Err(Error::Config(msg)) => assert!(msg.contains("reserved"), "{}", msg),
+                other => panic!("expected a configuration error, got {:?}", other),
+            }
+        }
+        let config = config("components = [\"Overall Health\"]");
+        assert!(check_components(&config).is_ok());
+    }
 }
@@ -0,0 +1,125 @@
+//! Atom and RSS feeds of the incidents, one pair for the whole status page
+//! and one for each component.
+//!
+//! The feeds are rendered from the `atom.xml` and `rss.xml` templates. Template
+//! directories without them use the built-in ones.
+
+use chrono::prelude::*;
+use chrono::serde::ts_seconds::serialize as to_seconds;
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::fs::create_dir_all;
+use std::path::Path;
+use tera::{Context, Tera};
+
+use crate::types::{Component, Components, Error, Incident, IncidentUpdate};
+use crate::writer::render;
+
+/// The built-in feed templates.
+pub const TEMPLATES: &[(&str, &str)] = &[
+    ("atom.xml", include_str!("../templates/atom.xml")),
+    ("rss.xml", include_str!("../templates/rss.xml")),
+];
+
+/// The template and extension of each feed.
+const FEEDS: &[(&str, &str)] = &[("atom.xml", "atom"), ("rss.xml", "rss")];
+
+#[derive(Debug, Serialize)]
+struct FeedEntry<'a> {
+    /// Unique within the feed: the incident page, followed by the time of
+    /// the update for update entries.
+    id: String,
+    incident: &'a Incident,
+    update: Option<&'a IncidentUpdate>,
+    #[serde(serialize_with = "to_seconds")]
+    updated: DateTime<Utc>,
+}
+
+/// The feed entries of `incidents`, newest first.
+fn get_entries<'a>(incidents: &[&'a Incident], per_update: bool) -> Vec<FeedEntry<'a>> {
+    let mut entries = Vec::new();
+    for incident in incidents.iter() {
+        let page = format!("{}.html", incident.id);
+        if per_update {
+            for update in incident.updates.iter() {
+                entries.push(FeedEntry {
+                    id: format!("{}#{}", page, update.datetime.timestamp()),
+                    incident,
+                    update: Some(update),
+                    updated: update.datetime,
+                });
+            }
+        } else {
+            entries.push(FeedEntry {
+                id: page,
+                incident,
+                update: None,
+                updated: incident.updated(),
+            });
+        }
+    }
+    entries.sort_by_key(|entry| Reverse(entry.updated));
+    entries
+}
+
+/// Writes `<path>.atom` and `<path>.rss`, `path` is relative to `output_dir`.
+fn render_feed(
+    tera: &Tera,
+    base: &Context,
+    component: Option<&Component>,
+    entries: &[FeedEntry],
+    output_dir: &Path,
+    path: &str,
+) -> Result<(), Error> {
+    let mut ctx = base.clone();
+    ctx.insert("component", &component);
+    ctx.insert("entries", entries);
+    // Links are relative to the feed unless the base URL is configured.
+    let relative_root = match path.matches('/').count() {
+        0 => String::from("./"),
+        depth => "../".repeat(depth),
+    };
+    ctx.insert("relative_root", &relative_root);
+    let updated = entries.first().map_or_else(Utc::now, |entry| entry.updated);
+    ctx.insert("updated", &updated.timestamp());
+    for (template, extension) in FEEDS.iter() {
+        let feed_path = format!("{}.{}", path, extension);
+        ctx.insert("feed_path", &feed_path);
+        render(tera, &ctx, template, output_dir.join(&feed_path))?;
+    }
+    Ok(())
+}
+
+/// Writes the feeds of all incidents and the feeds of each component in
+/// `feeds/`.
+pub fn render_feeds(
+    tera: &Tera,
+    base: &Context,
+    incidents: &[&Incident],
+    components: &Components,
+    per_update: bool,
+    output_dir: &Path,
+) -> Result<(), Error> {
+    let entries = get_entries(incidents, per_update);
+    render_feed(tera, base, None, &entries, output_dir, "feed")?;
+
+    let feeds_dir = output_dir.join("feeds");
+    create_dir_all(&feeds_dir).map_err(|e| Error::io(&feeds_dir, e))?;
+    for (slug, component) in components.iter().filter(|(_, c)| !c.hidden) {
+        let affected: Vec<&Incident> = incidents
+            .iter()
+            .copied()
+            .filter(|incident| incident.affects(slug))
+            .collect();
+        let entries = get_entries(&affected, per_update);
+        render_feed(
+            tera,
+            base,
+            Some(component),
+            &entries,
+            output_dir,
+            &format!("feeds/{}", slug),
+        )?;
+    }
+    Ok(())
+}
@@ -0,0 +1,479 @@
+//! Reads incidents from a directory of Markdown files, one file per incident.
+//!
+//! Each file starts with front matter, either TOML between `+++` lines or YAML
+//! between `---` lines:
+//!
+//! ```text
+//! +++
+//! title = "Database unavailable"
+//! severity = "major-outage"
+//! components = ["Database"]
+//! opened = 2022-03-01T10:00:00Z
+//! closed = 2022-03-01T12:30:00Z
+//! +++
+//! We are investigating connection errors.
+//!
+//! ---
+//! 2022-03-01T11:00:00Z
+//! The database has been restarted.
+//! ```
+//!
+//! The text after the front matter is the initial description. Every further
+//! update is separated by a `---` line and starts with its RFC 3339 timestamp.
+//! The severity (a status key) and components are turned into the same labels
+//! the issue tracker backends use, following the `[labels]` configuration. A
+//! component group stands for all of its components.
+//!
+//! Optional `labels` are added as they are, and the optional `author` is
+//! matched against the authors of the `[incidents]` filter. Updates count as
+//! written by the same author.
+//!
+//! Scheduled maintenance sets `start` and `end` instead of a severity. Such a
+//! file gets the maintenance label and stays open; it moves to the history
+//! once the window has ended.
+
+use chrono::prelude::*;
+use chrono::ParseResult;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::fs::{read_dir, read_to_string};
+use std::path::{Path, PathBuf};
+
+use crate::config::IncidentFilter;
+use crate::labels::LabelChange;
+use crate::provider::{
+    get_closed_incidents, get_open_incidents, Comment, Issue, IssueProvider, LabelScheme, Labels,
+};
+use crate::types::{Components, Error, Incident, MaintenanceWindow};
+
+/// TOML has a native datetime type, YAML only knows strings.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum FrontMatterDate {
+    Toml(toml::value::Datetime),
+    Text(String),
+}
+
+impl FrontMatterDate {
+    fn to_datetime(&self) -> ParseResult<DateTime<Utc>> {
+        match self {
+            FrontMatterDate::Toml(dt) => parse_rfc3339(&dt.to_string()),
+            FrontMatterDate::Text(s) => parse_rfc3339(s),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct FrontMatter {
+    author: Option<String>,
+    closed: Option<FrontMatterDate>,
+    #[serde(default)]
+    components: Vec<String>,
+    end: Option<FrontMatterDate>,
+    #[serde(default)]
+    labels: Vec<String>,
+    opened: FrontMatterDate,
+    severity: Option<String>,
+    start: Option<FrontMatterDate>,
+    title: String,
+}
+
+fn parse_rfc3339(s: &str) -> ParseResult<DateTime<Utc>> {
+    Ok(DateTime::from(DateTime::parse_from_rfc3339(s.trim())?))
+}
+
+/// File names that would overwrite the pages generated next to the incident
+/// pages, which are named after the incident ID.
+const RESERVED_IDS: &[&str] = &["index", "stats"];
+
+fn parse_file(path: &Path, scheme: &LabelScheme) -> Result<Issue, Error> {
+    let content = read_to_string(path).map_err(|e| Error::io(path, e))?;
+    parse_issue(&content, path, scheme)
+}
+
+/// Parses the `content` of the incident file at `path`.
+fn parse_issue(content: &str, path: &Path, scheme: &LabelScheme) -> Result<Issue, Error> {
+    let id = path
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned();
+    if RESERVED_IDS.contains(&id.to_lowercase().as_str()) {
+        return Err(Error::Parse(format!(
+            "The incident file {:?} would overwrite {}.html, please rename it.",
+            path, id
+        )));
+    }
+    let mut lines = content.lines();
+    let delimiter = lines.next().map(str::trim_end);
+    let mut front_matter = String::new();
+    for line in lines.by_ref() {
+        if Some(line.trim_end()) == delimiter {
+            break;
+        }
+        front_matter.push_str(line);
+        front_matter.push('\n');
+    }
+    let invalid = |e: &dyn std::fmt::Display| {
+        Error::Parse(format!("Invalid front matter in {:?}: {}", path, e))
+    };
+    let front_matter: FrontMatter = match delimiter {
+        Some("+++") => toml::from_str(&front_matter).map_err(|e| invalid(&e))?,
+        Some("---") => serde_yaml::from_str(&front_matter).map_err(|e| invalid(&e))?,
+        _ => {
+            return Err(Error::Parse(format!(
+                "File {:?} does not start with front matter.",
+                path
+            )));
+        }
+    };
+
+    let mut sections = vec![String::new()];
+    for line in lines {
+        if line.trim_end() == "---" {
+            sections.push(String::new());
+        } else {
+            let section = sections.last_mut().unwrap();
+            section.push_str(line);
+            section.push('\n');
+        }
+    }
+    let mut sections = sections.into_iter();
+    let body = String::from(sections.next().unwrap_or_default().trim());
+    let mut comments = Vec::new();
+    for section in sections {
+        let section = section.trim();
+        let (datetime, text) = section.split_once('\n').unwrap_or((section, ""));
+        comments.push(Comment {
+            author: front_matter.author.clone(),
+            body: String::from(text.trim()),
+            created_at: parse_rfc3339(datetime).map_err(|e| {
+                Error::Parse(format!(
+                    "Invalid update timestamp {:?} in {:?}: {}",
+                    datetime, path, e
+                ))
+            })?,
+            reactions: Vec::new(),
+        });
+    }
+
+    let mut labels = front_matter.labels;
+    if let Some(severity) = front_matter.severity {
+        match scheme.status_label_for_key(&severity) {
+            Some(label) => labels.push(label),
+            None => {
+                return Err(Error::Parse(format!(
+                    "Unknown severity {:?} in {:?}, expected one of: {}.",
+                    severity,
+                    path,
+                    scheme.status_keys().join(", ")
+                )));
+            }
+        }
+    }
+    let maintenance = match (front_matter.start, front_matter.end) {
+        (Some(start), Some(end)) => {
+            let start = start.to_datetime().map_err(|e| invalid(&e))?;
+            let end = end.to_datetime().map_err(|e| invalid(&e))?;
+            if start >= end {
+                return Err(Error::Parse(format!(
+                    "Maintenance in {:?} has to end after its start.",
+                    path
+                )));
+            }
+            labels.push(String::from(scheme.maintenance_label()));
+            Some(MaintenanceWindow { start, end })
+        }
+        (None, None) => None,
+        _ => {
+            return Err(Error::Parse(format!(
+                "Maintenance in {:?} needs both a start and an end.",
+                path
+            )));
+        }
+    };
+    for component in front_matter.components.iter() {
+        labels.push(scheme.component_label(&scheme.slug_for_name(component)));
+    }
+
+    let closed_at = match front_matter.closed {
+        Some(closed) => Some(closed.to_datetime().map_err(|e| invalid(&e))?),
+        None => None,
+    };
+    Ok(Issue {
+        author: front_matter.author,
+        body,
+        closed_at,
+        comments,
+        created_at: front_matter.opened.to_datetime().map_err(|e| invalid(&e))?,
+        id,
+        labels,
+        maintenance,
+        title: front_matter.title,
+    })
+}
+
+pub struct FilesIssueProvider {
+    directory: PathBuf,
+    filter: IncidentFilter,
+    open_incidents: Vec<Issue>,
+    closed_incidents: Vec<Issue>,
+    label_scheme: LabelScheme,
+}
+
+impl FilesIssueProvider {
+    pub fn new(directory: PathBuf, filter: IncidentFilter, label_scheme: LabelScheme) -> Self {
+        FilesIssueProvider {
+            directory,
+            filter,
+            open_incidents: Vec::new(),
+            closed_incidents: Vec::new(),
+            label_scheme,
+        }
+    }
+}
+
+impl IssueProvider for FilesIssueProvider {
+    fn label_scheme(&self) -> &LabelScheme {
+        &self.label_scheme
+    }
+    fn get_open_incidents(&self) -> Vec<Incident> {
+        get_open_incidents(
+            &self.open_incidents,
+            &self.label_scheme,
+            &self.filter.updates,
+        )
+    }
+    fn get_closed_incidents(&self) -> Vec<Incident> {
+        get_closed_incidents(
+            &self.closed_incidents,
+            &self.label_scheme,
+            &self.filter.updates,
+        )
+    }
+
+    fn fetch_incidents(&mut self) -> Result<(), Error> {
+        let mut paths = Vec::new();
+        let io_error = |e| Error::io(&self.directory, e);
+        for entry in read_dir(&self.directory).map_err(io_error)? {
+            let path = entry.map_err(io_error)?.path();
+            if path.is_file() && path.extension().is_some_and(|ext| ext == "md") {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+        for path in paths {
+            let issue = parse_file(&path, &self.label_scheme)?;
+            if !issue.matches(&self.filter) {
+                continue;
+            }
+            if issue.closed_at.is_some() {
+                self.closed_incidents.push(issue);
+            } else {
+                self.open_incidents.push(issue);
+            }
+        }
+        Ok(())
+    }
+
+    /// There is no label registry for files, any label is available.
+    fn fetch_labels(&self) -> Result<Labels, Error> {
+        Ok(Labels::new(HashSet::new(), HashSet::new()))
+    }
+
+    fn apply_label_change(&self, _change: &LabelChange) -> Result<(), Error> {
+        Err(Error::Config(String::from(
+            "The files backend has no labels that could be changed.",
+        )))
+    }
+
+    fn get_expected_labels(&self, _components: &Components) -> Labels {
+        Labels::new(HashSet::new(), HashSet::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::config;
+
+    fn parse(content: &str) -> Result<Issue, Error> {
+        parse_issue(
+            content,
+            Path::new("incident.md"),
+            &LabelScheme::new(&config("")),
+        )
+    }
+
+    #[test]
+    fn parses_toml_front_matter() {
+        let issue = parse(
+            "+++
+title = \"Database unavailable\"
+severity = \"major-outage\"
+components = [\"Database\"]
+opened = 2022-03-01T10:00:00Z
+closed = 2022-03-01T12:30:00Z
++++
+We are investigating.
+",
+        )
+        .unwrap();
+        assert_eq!(issue.id, "incident");
+        assert_eq!(issue.title, "Database unavailable");
+        assert_eq!(issue.body, "We are investigating.");
+        assert_eq!(issue.labels, ["status::major-outage", "component/database"]);
+        assert_eq!(
+            issue.closed_at.map(|closed| closed.to_rfc3339()),
+            Some(String::from("2022-03-01T12:30:00+00:00"))
+        );
+    }
+
+    #[test]
+    fn parses_yaml_front_matter() {
+        let issue = parse(
+            "---
+title: Slow website
+severity: degraded-performance
+components: [Website]
+author: alice
+opened: 2022-03-01T10:00:00Z
+---
+Pages load slowly.
+",
+        )
+        .unwrap();
+        assert_eq!(issue.title, "Slow website");
+        assert_eq!(issue.author.as_deref(), Some("alice"));
+        assert_eq!(
+            issue.labels,
+            ["status::degraded-performance", "component/website"]
+        );
+        assert!(issue.closed_at.is_none());
+    }
+
+    #[test]
+    fn splits_updates() {
+        let issue = parse(
+            "---
+title: Outage
+opened: 2022-03-01T10:00:00Z
+---
+Investigating.
+
+---
+2022-03-01T11:00:00Z
+Identified the cause.
+---
+2022-03-01T12:00:00Z
+Fixed.
+",
+        )
+        .unwrap();
+        assert_eq!(issue.body, "Investigating.");
+        let updates: Vec<(String, &str)> = issue
+            .comments
+            .iter()
+            .map(|comment| (comment.created_at.to_rfc3339(), comment.body.as_str()))
+            .collect();
+        assert_eq!(
+            updates,
+            [
+                (
+                    String::from("2022-03-01T11:00:00+00:00"),
+                    "Identified the cause."
+                ),
+                (String::from("2022-03-01T12:00:00+00:00"), "Fixed."),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_bad_update_timestamp() {
+        let result = parse(
+            "---
+title: Outage
+opened: 2022-03-01T10:00:00Z
+---
+Investigating.
+---
+yesterday
+Fixed.
+",
+        );
+        match result {
+            Err(Error::Parse(msg)) => assert!(msg.contains("\"yesterday\""), "{}", msg),
+            other => panic!("expected a parse error, got {:?}", other.map(|i| i.id)),
+        }
+    }
+
+    #[test]
+    fn rejects_maintenance_without_end() {
+        let result = parse(
+            "---
+title: Upgrade
+opened: 2022-03-01T10:00:00Z
+start: 2022-03-02T10:00:00Z
+---
+Upgrading the database.
+",
+        );
+        match result {
+            Err(Error::Parse(msg)) => assert!(msg.contains("start and an end"), "{}", msg),
+            other => panic!("expected a parse error, got {:?}", other.map(|i| i.id)),
+        }
+    }
+
+    #[test]
+    fn rejects_maintenance_ending_before_start() {
+        let result = parse(
+            "---
+title: Upgrade
+opened: 2022-03-01T10:00:00Z
+start: 2022-03-02T12:00:00Z
+end: 2022-03-02T10:00:00Z
+---
+Upgrading the database.
+",
+        );
+        match result {
+            Err(Error::Parse(msg)) => assert!(msg.contains("end after its start"), "{}", msg),
+            other => panic!("expected a parse error, got {:?}", other.map(|i| i.id)),
+        }
+    }
+
+    #[test]
+    fn rejects_reserved_file_names() {
+        for name in ["index.md", "stats.md", "Index.md"] {
+            let result = parse_issue(
+                "---\ntitle: Outage\nopened: 2022-03-01T10:00:00Z\n---\nDown.\n",
+                Path::new(name),
+                &LabelScheme::new(&config("")),
+            );
+            match result {
+                Err(Error::Parse(msg)) => assert!(msg.contains("would overwrite"), "{}", msg),
+                other => panic!("expected a parse error, got {:?}", other.map(|i| i.id)),
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_severity() {
+        let result = parse(
+            "---
+title: Outage
+severity: down
+opened: 2022-03-01T10:00:00Z
+---
+Down.
+",
+        );
+        match result {
+            Err(Error::Parse(msg)) => {
+                assert!(msg.contains("\"down\""), "{}", msg);
+                assert!(msg.contains("major-outage"), "{}", msg);
+            }
+            other => panic!("expected a parse error, got {:?}", other.map(|i| i.id)),
+        }
+    }
+}
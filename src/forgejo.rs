@@ -0,0 +1,360 @@
+use chrono::prelude::*;
+use reqwest::blocking::{Client, Response};
+use reqwest::Url;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use crate::config::IncidentFilter;
+use crate::labels::LabelChange;
+use crate::provider::{
+    get_closed_incidents, get_open_incidents, get_token, Comment, Issue, IssueProvider,
+    LabelScheme, Labels,
+};
+use crate::types::{Error, Incident};
+use crate::utils::{parse_datetime, parse_datetime_optional};
+
+const PAGE_SIZE: usize = 50;
+
+#[derive(Debug, Deserialize)]
+struct FJLabel {
+    id: u64,
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct FJComment {
+    body: String,
+    #[serde(deserialize_with = "parse_datetime")]
+    created_at: DateTime<Utc>,
+    id: u64,
+    user: Option<FJUser>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FJReaction {
+    content: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct FJUser {
+    login: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct FJIssue {
+    body: Option<String>,
+    #[serde(deserialize_with = "parse_datetime_optional")]
+    closed_at: Option<DateTime<Utc>>,
+    #[serde(deserialize_with = "parse_datetime")]
+    created_at: DateTime<Utc>,
+    labels: Vec<FJLabel>,
+    number: u64,
+    title: String,
+    user: Option<FJUser>,
+}
+
+/// Reads incidents from the REST API of a Forgejo or Gitea instance.
+pub struct ForgejoIssueProvider {
+    url: Url,
+    owner: String,
+    repository: String,
+    filter: IncidentFilter,
+    open_incidents: Vec<Issue>,
+    closed_incidents: Vec<Issue>,
+    label_scheme: LabelScheme,
+}
+
+impl ForgejoIssueProvider {
+    pub fn new(
+        url: String,
+        owner: String,
+        repository: String,
+        filter: IncidentFilter,
+        label_scheme: LabelScheme,
+    ) -> Result<Self, Error> {
+        let url = match Url::parse(&url) {
+            Ok(url) if !url.cannot_be_a_base() => url,
+            _ => {
+                return Err(Error::Config(format!("Invalid Forgejo URL {:?}.", url)));
+            }
+        };
+        Ok(ForgejoIssueProvider {
+            url,
+            owner,
+            repository,
+            filter,
+            open_incidents: Vec::new(),
+            closed_incidents: Vec::new(),
+            label_scheme,
+        })
+    }
+
+    fn client(&self) -> Result<Client, Error> {
+        Ok(Client::builder().user_agent("StatusPageRS/0.1.0").build()?)
+    }
+
+    /// Builds the URL `<url>/api/v1/repos/<owner>/<repository>/<path...>`.
+    fn repo_url(&self, path: &[&str]) -> Url {
+        let mut url = self.url.clone();
+        url.path_segments_mut()
+            .expect("URL is validated in new()")
+            .pop_if_empty()
+            .extend(&["api", "v1", "repos", &self.owner, &self.repository])
+            .extend(path);
+        url
+    }
+
+    fn send_get(&self, client: &Client, url: Url) -> Result<Response, Error> {
+        let forgejo_token = get_token("FORGEJO_TOKEN")?;
+        Ok(client
+            .get(url)
+            .header("Authorization", format!("token {}", forgejo_token))
+            .send()?
+            .error_for_status()?)
+    }
+
+    fn get<T: DeserializeOwned>(&self, client: &Client, url: Url) -> Result<T, Error> {
+        Ok(self.send_get(client, url)?.json()?)
+    }
+
+    /// Fetches all pages of a list endpoint. The server may cap the page size
+    /// below the requested one (`MAX_RESPONSE_ITEMS`), so a short page doesn't
+    /// mean the end: fetching stops once `X-Total-Count` items arrived, or at
+    /// the first empty page if the header is missing.
+    fn get_all<T: DeserializeOwned>(&self, client: &Client, url: Url) -> Result<Vec<T>, Error> {
+        let mut items = Vec::new();
+        let mut page = 1;
+        loop {
+            let mut page_url = url.clone();
+            page_url
+                .query_pairs_mut()
+                .append_pair("limit", &PAGE_SIZE.to_string())
+                .append_pair("page", &page.to_string());
+            let resp = self.send_get(client, page_url)?;
+            let total: Option<usize> = resp
+                .headers()
+                .get("x-total-count")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse().ok());
+            let mut batch: Vec<T> = resp.json()?;
+            let empty = batch.is_empty();
+            items.append(&mut batch);
+            if empty || total.is_some_and(|total| items.len() >= total) {
+                break;
+            }
+            page += 1;
+        }
+        Ok(items)
+    }
+
+    fn find_label(&self, client: &Client, name: &str) -> Result<FJLabel, Error> {
+        let labels: Vec<FJLabel> = self.get_all(client, self.repo_url(&["labels"]))?;
+        labels
+            .into_iter()
+            .find(|label| label.name == name)
+            .ok_or_else(|| Error::Api(format!("Label {} does not exist.", name)))
+    }
+
+    fn fetch_issues(&self, client: &Client, state: &str) -> Result<Vec<Issue>, Error> {
+        let mut url = self.repo_url(&["issues"]);
+        url.query_pairs_mut()
+            .append_pair("state", state)
+            .append_pair("type", "issues");
+        if let Some(label) = &self.filter.label {
+            url.query_pairs_mut().append_pair("labels", label);
+        }
+        let issues: Vec<FJIssue> = self.get_all(client, url)?;
+        let mut result = Vec::with_capacity(issues.len());
+        for issue in issues {
+            let mut issue = Issue {
+                author: issue.user.map(|user| user.login),
+                body: issue.body.unwrap_or_default(),
+                closed_at: issue.closed_at,
+                comments: Vec::new(),
+                created_at: issue.created_at,
+                id: issue.number.to_string(),
+                labels: issue.labels.into_iter().map(|label| label.name).collect(),
+                maintenance: None,
+                title: issue.title,
+            };
+            if !issue.matches(&self.filter) {
+                continue;
+            }
+            // The comments endpoint is not paginated and returns all comments.
+            let url = self.repo_url(&["issues", &issue.id, "comments"]);
+            let comments: Vec<FJComment> = self.get(client, url)?;
+            for comment in comments {
+                // Reactions need a request per comment, skip them unless needed.
+                let mut reactions = Vec::new();
+                if !self.filter.updates.exclude_reactions.is_empty() {
+                    let url = self.repo_url(&[
+                        "issues",
+                        "comments",
+                        &comment.id.to_string(),
+                        "reactions",
+                    ]);
+                    let comment_reactions: Option<Vec<FJReaction>> = self.get(client, url)?;
+                    reactions = comment_reactions
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(|reaction| reaction.content)
+                        .collect();
+                }
+                issue.comments.push(Comment {
+                    author: comment.user.map(|user| user.login),
+                    body: comment.body,
+                    created_at: comment.created_at,
+                    reactions,
+                });
+            }
+            result.push(issue);
+        }
+        Ok(result)
+    }
+}
+
+impl IssueProvider for ForgejoIssueProvider {
+    fn label_scheme(&self) -> &LabelScheme {
+        &self.label_scheme
+    }
+    fn get_open_incidents(&self) -> Vec<Incident> {
+        get_open_incidents(
+            &self.open_incidents,
+            &self.label_scheme,
+            &self.filter.updates,
+        )
+    }
+    fn get_closed_incidents(&self) -> Vec<Incident> {
+        get_closed_incidents(
+            &self.closed_incidents,
+            &self.label_scheme,
+            &self.filter.updates,
+        )
+    }
+
+    fn fetch_incidents(&mut self) -> Result<(), Error> {
+        let client = self.client()?;
+        let mut open_incidents = self.fetch_issues(&client, "open")?;
+        let mut closed_incidents = self.fetch_issues(&client, "closed")?;
+        self.open_incidents.append(&mut open_incidents);
+        self.closed_incidents.append(&mut closed_incidents);
+        Ok(())
+    }
+
+    fn fetch_labels(&self) -> Result<Labels, Error> {
+        let client = self.client()?;
+        let labels: Vec<FJLabel> = self.get_all(&client, self.repo_url(&["labels"]))?;
+        Ok(Labels::from_names(
+            labels.into_iter().map(|label| label.name),
+            &self.label_scheme,
+        ))
+    }
+
+    fn apply_label_change(&self, change: &LabelChange) -> Result<(), Error> {
+        let forgejo_token = get_token("FORGEJO_TOKEN")?;
+        let client = self.client()?;
+        // Existing labels can only be addressed by their ID.
+        let request = match change {
+            LabelChange::Create {
+                name,
+                color,
+                description,
+            } => client
+                .post(self.repo_url(&["labels"]))
+                .json(&serde_json::json!({
+                    "name": name,
+                    "color": format!("#{}", color),
+                    "description": description,
+                })),
+            LabelChange::Delete { name } => {
+                let id = self.find_label(&client, name)?.id.to_string();
+                client.delete(self.repo_url(&["labels", &id]))
+            }
+            LabelChange::Rename { name, new_name } => {
+                let id = self.find_label(&client, name)?.id.to_string();
+                client
+                    .patch(self.repo_url(&["labels", &id]))
+                    .json(&serde_json::json!({ "name": new_name }))
+            }
+        };
+        request
+            .header("Authorization", format!("token {}", forgejo_token))
+            .send()?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use std::env;
+
+    use super::*;
+    use crate::testing::{config, MockResponse, MockServer};
+
+    fn provider(server: &MockServer) -> ForgejoIssueProvider {
+        env::set_var("FORGEJO_TOKEN", "test-token");
+        let config = config("");
+        ForgejoIssueProvider::new(
+            server.url.clone(),
+            String::from("owner"),
+            String::from("repository"),
+            config.incidents.clone(),
+            LabelScheme::new(&config),
+        )
+        .unwrap()
+    }
+
+    /// Five labels, served two per page like an instance with
+    /// `MAX_RESPONSE_ITEMS = 2`.
+    fn labels_page(url: &str) -> serde_json::Value {
+        let page: usize = url
+            .split(['?', '&'])
+            .find_map(|pair| pair.strip_prefix("page="))
+            .and_then(|page| page.parse().ok())
+            .unwrap_or(1);
+        let labels: Vec<serde_json::Value> = (1..=5)
+            .skip((page - 1) * 2)
+            .take(2)
+            .map(|id| json!({"id": id, "name": format!("component/c{}", id)}))
+            .collect();
+        json!(labels)
+    }
+
+    fn fetched_labels(server: &MockServer) -> Vec<String> {
+        let mut labels: Vec<String> = provider(server)
+            .fetch_labels()
+            .unwrap()
+            .components
+            .into_iter()
+            .collect();
+        labels.sort();
+        labels
+    }
+
+    #[test]
+    fn follows_total_count_past_short_pages() {
+        let server = MockServer::start(|request| {
+            MockResponse::json(labels_page(&request.url)).header("X-Total-Count", "5")
+        });
+        assert_eq!(fetched_labels(&server).len(), 5);
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[test]
+    fn stops_at_empty_page_without_total_count() {
+        let server = MockServer::start(|request| MockResponse::json(labels_page(&request.url)));
+        assert_eq!(
+            fetched_labels(&server),
+            [
+                "component/c1",
+                "component/c2",
+                "component/c3",
+                "component/c4",
+                "component/c5"
+            ]
+        );
+        assert_eq!(server.requests().len(), 4);
+    }
+}
@@ -1,18 +1,205 @@
 use chrono::prelude::*;
-use reqwest;
-use serde::{Deserialize, Deserializer};
-use serde_json;
-use std::collections::{HashMap, HashSet};
-use std::env;
+use reqwest::blocking::Client;
+use reqwest::header::HeaderMap;
+use reqwest::{StatusCode, Url};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+#[cfg(not(test))]
+use std::thread::sleep;
+use std::time::Duration;
 
-use crate::provider::{GraphQLRequest, IssueProvider, Labels};
-use crate::types::{Component, ComponentStatus, Error, Incident, IncidentUpdate};
+use crate::config::IncidentFilter;
+use crate::labels::LabelChange;
+use crate::provider::{
+    get_closed_incidents, get_open_incidents, get_token, Comment, GraphQLRequest, Issue,
+    IssueProvider, LabelScheme, Labels,
+};
+use crate::types::{Error, Incident};
+use crate::utils::{is_verbose, parse_datetime, parse_datetime_optional};
+#[cfg(test)]
+use tests::sleep;
 
-const LABEL_COMPONENT_PREFIX: &'static str = "component/";
-const LABEL_STATUS_PREFIX: &'static str = "status::";
-const LABEL_STATUS_OPERATIONAL: &'static str = "status::operational";
-const LABEL_STATUS_PARTIAL_OUTAGE: &'static str = "status::partial-outage";
-const LABEL_STATUS_MAJOR_OUTAGE: &'static str = "status::major-outage";
+/// How often a request is retried after hitting a rate limit or server error.
+const MAX_RETRIES: u32 = 5;
+/// Waiting longer than this for a rate limit reset is not worth it, better
+/// fail and let the next run try again.
+const MAX_WAIT: Duration = Duration::from_secs(15 * 60);
+/// GitHub asks to wait at least a minute after hitting a secondary rate limit
+/// that doesn't say how long to wait.
+const RATE_LIMIT_WAIT: Duration = Duration::from_secs(60);
+
+/// The fields fetched for every issue, shared by [`ISSUES_QUERY`] and
+/// [`SEARCH_QUERY`].
+macro_rules! issue_fields {
+    () => {
+        r#"
+  fragment IssueFields on Issue {
+    author {
+      login
+    }
+    body
+    closedAt
+    comments(first: 100) {
+      pageInfo {
+        hasNextPage
+        endCursor
+      }
+      nodes {
+        author {
+          login
+        }
+        body
+        createdAt
+        reactionGroups {
+          content
+          reactors {
+            totalCount
+          }
+        }
+      }
+    }
+    createdAt
+    id
+    labels(first: 100) {
+      pageInfo {
+        hasNextPage
+        endCursor
+      }
+      nodes {
+        name
+      }
+    }
+    title
+  }
+"#
+    };
+}
+
+const ISSUES_QUERY: &str = concat!(
+    r#"
+  query($repository: String!, $owner: String!, $states: [IssueState!], $cursor: String){
+    repository(name: $repository, owner: $owner) {
+      issues(first: 100, states: $states, after: $cursor, orderBy: {field: CREATED_AT, direction: DESC}) {
+        pageInfo {
+          hasNextPage
+          endCursor
+        }
+        nodes {
+          ...IssueFields
+        }
+      }
+    }
+  }
+"#,
+    issue_fields!()
+);
+
+const SEARCH_QUERY: &str = concat!(
+    r#"
+  query($query: String!, $cursor: String){
+    search(query: $query, type: ISSUE, first: 100, after: $cursor) {
+      pageInfo {
+        hasNextPage
+        endCursor
+      }
+      nodes {
+        ...IssueFields
+      }
+    }
+  }
+"#,
+    issue_fields!()
+);
+
+const ISSUE_COMMENTS_QUERY: &str = r#"
+  query($id: ID!, $cursor: String){
+    node(id: $id) {
+      ... on Issue {
+        comments(first: 100, after: $cursor) {
+          pageInfo {
+            hasNextPage
+            endCursor
+          }
+          nodes {
+            author {
+              login
+            }
+            body
+            createdAt
+            reactionGroups {
+              content
+              reactors {
+                totalCount
+              }
+            }
+          }
+        }
+      }
+    }
+  }
+"#;
+
+const ISSUE_LABELS_QUERY: &str = r#"
+  query($id: ID!, $cursor: String){
+    node(id: $id) {
+      ... on Issue {
+        labels(first: 100, after: $cursor) {
+          pageInfo {
+            hasNextPage
+            endCursor
+          }
+          nodes {
+            name
+          }
+        }
+      }
+    }
+  }
+"#;
+
+const LABELS_QUERY: &str = r#"
+  query($repository: String!, $owner: String!, $cursor: String){
+    repository(name: $repository, owner: $owner) {
+      labels(first: 100, after: $cursor) {
+        pageInfo {
+          hasNextPage
+          endCursor
+        }
+        nodes {
+          name
+        }
+      }
+    }
+  }
+"#;
+
+#[derive(Debug, Deserialize)]
+struct GQLPageInfo {
+    #[serde(rename = "hasNextPage")]
+    has_next_page: bool,
+    #[serde(rename = "endCursor")]
+    end_cursor: Option<String>,
+}
+
+impl GQLPageInfo {
+    /// The cursor to continue with, if there is another page.
+    fn next_cursor(&self) -> Option<String> {
+        if self.has_next_page {
+            self.end_cursor.clone()
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct GQLConnection<T> {
+    #[serde(rename = "pageInfo")]
+    page_info: GQLPageInfo,
+    nodes: Vec<T>,
+}
 
 #[derive(Debug, Deserialize)]
 struct GQLLabel {
@@ -20,315 +207,738 @@ struct GQLLabel {
 }
 
 #[derive(Debug, Deserialize)]
-struct GQLLabelNode {
-    nodes: Vec<GQLLabel>,
+struct GQLTotalCount {
+    #[serde(rename = "totalCount")]
+    total_count: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct GQLReactionGroup {
+    content: String,
+    reactors: GQLTotalCount,
 }
+
 #[derive(Debug, Deserialize)]
 struct GQLComment {
+    author: Option<GQLActor>,
     body: String,
     #[serde(rename = "createdAt", deserialize_with = "parse_datetime")]
     created_at: DateTime<Utc>,
+    #[serde(rename = "reactionGroups", default)]
+    reaction_groups: Option<Vec<GQLReactionGroup>>,
+}
+
+/// Maps a GraphQL `ReactionContent` to the name the REST API uses.
+fn reaction_name(content: &str) -> String {
+    match content {
+        "THUMBS_UP" => String::from("+1"),
+        "THUMBS_DOWN" => String::from("-1"),
+        other => other.to_lowercase(),
+    }
+}
+
+impl From<GQLComment> for Comment {
+    fn from(comment: GQLComment) -> Self {
+        Comment {
+            author: comment.author.map(|author| author.login),
+            body: comment.body,
+            created_at: comment.created_at,
+            reactions: comment
+                .reaction_groups
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|group| group.reactors.total_count > 0)
+                .map(|group| reaction_name(&group.content))
+                .collect(),
+        }
+    }
 }
+
 #[derive(Debug, Deserialize)]
-struct GQLCommentNode {
-    nodes: Vec<GQLComment>,
+struct GQLActor {
+    login: String,
 }
 
 #[derive(Debug, Deserialize)]
 struct GQLIncident {
+    author: Option<GQLActor>,
     body: String,
     #[serde(rename = "closedAt", deserialize_with = "parse_datetime_optional")]
     closed_at: Option<DateTime<Utc>>,
-    comments: GQLCommentNode,
+    comments: GQLConnection<GQLComment>,
     #[serde(rename = "createdAt", deserialize_with = "parse_datetime")]
     created_at: DateTime<Utc>,
     id: String,
-    labels: GQLLabelNode,
+    labels: GQLConnection<GQLLabel>,
     title: String,
 }
 
+impl From<GQLIncident> for Issue {
+    fn from(incident: GQLIncident) -> Self {
+        Issue {
+            author: incident.author.map(|author| author.login),
+            body: incident.body,
+            closed_at: incident.closed_at,
+            comments: incident
+                .comments
+                .nodes
+                .into_iter()
+                .map(Comment::from)
+                .collect(),
+            created_at: incident.created_at,
+            id: incident.id,
+            labels: incident
+                .labels
+                .nodes
+                .into_iter()
+                .map(|label| label.name)
+                .collect(),
+            maintenance: None,
+            title: incident.title,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct GQLIssuesRepository {
+    issues: GQLConnection<GQLIncident>,
+}
+
 #[derive(Debug, Deserialize)]
-struct GQLIncidentNode {
-    nodes: Vec<GQLIncident>,
+struct GQLSearchData {
+    search: GQLConnection<GQLIncident>,
 }
 
 #[derive(Debug, Deserialize)]
-struct GQLRepository {
-    #[serde(rename = "openIncidents")]
-    open_incidents: GQLIncidentNode,
-    #[serde(rename = "closedIncidents")]
-    closed_incidents: GQLIncidentNode,
+struct GQLLabelsRepository {
+    labels: GQLConnection<GQLLabel>,
 }
 
 #[derive(Debug, Deserialize)]
-struct GQLData {
-    repository: GQLRepository,
+struct GQLIssueComments {
+    comments: GQLConnection<GQLComment>,
 }
 
 #[derive(Debug, Deserialize)]
-struct GQLRoot {
-    data: GQLData,
+struct GQLIssueLabels {
+    labels: GQLConnection<GQLLabel>,
 }
 
-fn parse_datetime_optional<'de, D>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    match Option::deserialize(deserializer).unwrap() {
-        None => Ok(None),
-        Some(s) => Ok(Some(DateTime::from(
-            DateTime::parse_from_rfc3339(s).unwrap(),
-        ))),
+#[derive(Debug, Deserialize)]
+struct GQLRepositoryData<T> {
+    repository: T,
+}
+
+#[derive(Debug, Deserialize)]
+struct GQLNodeData<T> {
+    node: T,
+}
+
+#[derive(Debug, Deserialize)]
+struct GQLError {
+    message: String,
+    #[serde(rename = "type")]
+    error_type: Option<String>,
+    path: Option<Vec<serde_json::Value>>,
+}
+
+impl fmt::Display for GQLError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(error_type) = &self.error_type {
+            write!(f, "{}: ", error_type)?;
+        }
+        write!(f, "{}", self.message)?;
+        if let Some(path) = &self.path {
+            let path: Vec<String> = path
+                .iter()
+                .map(|segment| match segment {
+                    serde_json::Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                })
+                .collect();
+            write!(f, " (at {})", path.join("."))?;
+        }
+        Ok(())
     }
 }
 
-fn parse_datetime<'de, D>(deserializer: D) -> Result<DateTime<Utc>, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    let s = Deserialize::deserialize(deserializer).unwrap();
-    Ok(DateTime::from(DateTime::parse_from_rfc3339(s).unwrap()))
+#[derive(Debug, Deserialize)]
+struct GQLRoot<T> {
+    data: Option<T>,
+    errors: Option<Vec<GQLError>>,
+}
+
+/// The rate limit budget GitHub reports in the `X-RateLimit-*` headers.
+#[derive(Debug)]
+struct RateLimit {
+    limit: u64,
+    remaining: u64,
+    reset: DateTime<Utc>,
+}
+
+impl RateLimit {
+    fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let get = |name: &str| -> Option<i64> { headers.get(name)?.to_str().ok()?.parse().ok() };
+        Some(RateLimit {
+            limit: get("x-ratelimit-limit")? as u64,
+            remaining: get("x-ratelimit-remaining")? as u64,
+            reset: Utc.timestamp_opt(get("x-ratelimit-reset")?, 0).single()?,
+        })
+    }
+}
+
+impl fmt::Display for RateLimit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}/{} points remaining, resets at {}",
+            self.remaining,
+            self.limit,
+            self.reset.to_rfc3339()
+        )
+    }
+}
+
+/// Determines how long to wait before retrying a failed request: the
+/// `Retry-After` header wins, then the rate limit reset time, and otherwise an
+/// exponential backoff starting at `initial`.
+fn backoff(
+    attempt: u32,
+    reason: &str,
+    headers: &HeaderMap,
+    rate_limit: Option<&RateLimit>,
+    initial: Duration,
+) -> Result<Duration, Error> {
+    if attempt >= MAX_RETRIES {
+        return Err(Error::Api(format!(
+            "GitHub {}, giving up after {} retries.",
+            reason, MAX_RETRIES
+        )));
+    }
+    let retry_after = headers
+        .get("retry-after")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+        .map(Duration::from_secs);
+    let wait = match (retry_after, rate_limit) {
+        (Some(retry_after), _) => retry_after,
+        (None, Some(rate_limit)) if rate_limit.remaining == 0 => (rate_limit.reset - Utc::now())
+            .to_std()
+            .unwrap_or_default()
+            .saturating_add(Duration::from_secs(1)),
+        _ => initial.saturating_mul(2u32.pow(attempt)),
+    };
+    if wait > MAX_WAIT {
+        return Err(Error::Api(format!(
+            "GitHub {}, retrying is only possible in {} seconds.",
+            reason,
+            wait.as_secs()
+        )));
+    }
+    Ok(wait)
 }
 
 pub struct GitHubIssueProvider {
     owner: String,
     repository: String,
-    open_incidents: Vec<GQLIncident>,
-    closed_incidents: Vec<GQLIncident>,
+    graphql_url: String,
+    rest_url: String,
+    history_limit: Option<usize>,
+    filter: IncidentFilter,
+    open_incidents: Vec<Issue>,
+    closed_incidents: Vec<Issue>,
+    label_scheme: LabelScheme,
 }
 
 impl GitHubIssueProvider {
-    pub fn new(owner: String, repository: String) -> Self {
+    pub fn new(
+        owner: String,
+        repository: String,
+        graphql_url: String,
+        rest_url: String,
+        history_limit: Option<usize>,
+        filter: IncidentFilter,
+        label_scheme: LabelScheme,
+    ) -> Self {
         GitHubIssueProvider {
             owner,
             repository,
+            graphql_url,
+            rest_url,
+            history_limit,
+            filter,
             open_incidents: Vec::new(),
             closed_incidents: Vec::new(),
+            label_scheme,
         }
     }
 
-    fn get_status(&self, labels: &Vec<GQLLabel>) -> ComponentStatus {
-        let mut highest_status = ComponentStatus::Unknown;
-        for label in labels.iter() {
-            if !label.name.starts_with(LABEL_STATUS_PREFIX) {
-                continue;
+    fn client(&self) -> Result<Client, Error> {
+        Ok(Client::builder().user_agent("StatusPageRS/0.1.0").build()?)
+    }
+
+    fn query<T: DeserializeOwned>(
+        &self,
+        client: &Client,
+        params: &GraphQLRequest,
+    ) -> Result<T, Error> {
+        let github_token = get_token("GITHUB_TOKEN")?;
+        let mut attempt = 0;
+        loop {
+            let resp = client
+                .post(&self.graphql_url)
+                .bearer_auth(&github_token)
+                .json(params)
+                .send()?;
+            let headers = resp.headers().clone();
+            let rate_limit = RateLimit::from_headers(&headers);
+            if let Some(rate_limit) = &rate_limit {
+                if is_verbose() {
+                    eprintln!("GitHub API rate limit: {}", rate_limit);
+                }
             }
-            let this_status = match label.name.as_str() {
-                LABEL_STATUS_OPERATIONAL => ComponentStatus::Operational,
-                LABEL_STATUS_PARTIAL_OUTAGE => ComponentStatus::PartialOutage,
-                LABEL_STATUS_MAJOR_OUTAGE => ComponentStatus::MajorOutage,
-                _ => ComponentStatus::Unknown,
+
+            let status = resp.status();
+            let retry = if status == StatusCode::FORBIDDEN
+                || status == StatusCode::TOO_MANY_REQUESTS
+            {
+                // GitHub answers with 403 for both, exceeded rate limits and
+                // missing permissions. Only the former is worth a retry.
+                // Secondary rate limits only say so in the message.
+                let message = resp.text()?;
+                let rate_limited = status == StatusCode::TOO_MANY_REQUESTS
+                    || headers.contains_key("retry-after")
+                    || rate_limit.as_ref().is_some_and(|rl| rl.remaining == 0)
+                    || message.to_lowercase().contains("rate limit");
+                if !rate_limited {
+                    return Err(Error::Api(format!(
+                        "GitHub request failed ({}): {}",
+                        status,
+                        message.trim()
+                    )));
+                }
+                Some((format!("rate limit exceeded ({})", status), RATE_LIMIT_WAIT))
+            } else if status.is_server_error() {
+                Some((format!("server error ({})", status), Duration::from_secs(1)))
+            } else {
+                let root: GQLRoot<T> = resp.error_for_status()?.json()?;
+                match (root.data, root.errors) {
+                    (_, Some(errors))
+                        if errors
+                            .iter()
+                            .any(|e| e.error_type.as_deref() == Some("RATE_LIMITED")) =>
+                    {
+                        Some((String::from("GraphQL rate limit exceeded"), RATE_LIMIT_WAIT))
+                    }
+                    (_, Some(errors)) if !errors.is_empty() => {
+                        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+                        return Err(Error::Api(format!(
+                            "GitHub GraphQL query failed: {}",
+                            messages.join("; ")
+                        )));
+                    }
+                    (Some(data), _) => return Ok(data),
+                    (None, _) => {
+                        return Err(Error::Api(String::from(
+                            "GitHub GraphQL response contains neither data nor errors.",
+                        )));
+                    }
+                }
             };
-            if this_status > highest_status {
-                highest_status = this_status;
+
+            if let Some((reason, initial)) = retry {
+                let wait = backoff(attempt, &reason, &headers, rate_limit.as_ref(), initial)?;
+                eprintln!(
+                    "GitHub {}, retrying in {} seconds ...",
+                    reason,
+                    wait.as_secs()
+                );
+                sleep(wait);
+                attempt += 1;
             }
         }
-        highest_status
     }
-}
 
-impl IssueProvider for GitHubIssueProvider {
-    fn get_open_incidents(&self) -> Vec<Incident> {
-        let mut incidents = Vec::with_capacity(self.open_incidents.len());
-        for open_incident in self.open_incidents.iter() {
-            let mut updates = Vec::with_capacity(open_incident.comments.nodes.len() + 1);
-            updates.push(IncidentUpdate::new(
-                open_incident.created_at,
-                open_incident.body.clone(),
-            ));
-            for update in open_incident.comments.nodes.iter() {
-                updates.push(IncidentUpdate::new(update.created_at, update.body.clone()));
+    /// Builds the REST API URL `<rest_url>/repos/<owner>/<repository>/labels[/<name>]`.
+    fn label_url(&self, name: Option<&str>) -> Result<Url, Error> {
+        let invalid = || Error::Config(format!("Invalid GitHub REST URL {:?}.", self.rest_url));
+        let mut url = Url::parse(&self.rest_url).map_err(|_| invalid())?;
+        {
+            let mut segments = url.path_segments_mut().map_err(|_| invalid())?;
+            segments
+                .pop_if_empty()
+                .extend(&["repos", &self.owner, &self.repository, "labels"]);
+            if let Some(name) = name {
+                segments.push(name);
             }
-            updates.sort();
-            updates.reverse();
-            let mut component_names = Vec::new();
-            for component_name in open_incident.labels.nodes.iter() {
-                if !component_name.name.starts_with(LABEL_COMPONENT_PREFIX) {
+        }
+        Ok(url)
+    }
+
+    fn repository_variables(&self, cursor: Option<String>) -> HashMap<&'static str, String> {
+        let mut variables = HashMap::with_capacity(4);
+        variables.insert("repository", self.repository.clone());
+        variables.insert("owner", self.owner.clone());
+        if let Some(cursor) = cursor {
+            variables.insert("cursor", cursor);
+        }
+        variables
+    }
+
+    fn node_variables(id: &str, cursor: String) -> HashMap<&'static str, String> {
+        let mut variables = HashMap::with_capacity(2);
+        variables.insert("id", String::from(id));
+        variables.insert("cursor", cursor);
+        variables
+    }
+
+    /// Fetches one page of issues in the given state, newest first. With a
+    /// search query in the incident filter, the search API selects the
+    /// issues instead.
+    fn fetch_issues_page(
+        &self,
+        client: &Client,
+        state: &str,
+        cursor: Option<String>,
+    ) -> Result<GQLConnection<GQLIncident>, Error> {
+        match &self.filter.query {
+            Some(query) => {
+                let mut variables = HashMap::with_capacity(2);
+                variables.insert(
+                    "query",
+                    format!(
+                        "repo:{}/{} is:issue is:{} sort:created-desc {}",
+                        self.owner,
+                        self.repository,
+                        state.to_lowercase(),
+                        query
+                    ),
+                );
+                if let Some(cursor) = cursor {
+                    variables.insert("cursor", cursor);
+                }
+                let data: GQLSearchData =
+                    self.query(client, &GraphQLRequest::new(SEARCH_QUERY, variables))?;
+                Ok(data.search)
+            }
+            None => {
+                let mut variables = self.repository_variables(cursor);
+                variables.insert("states", String::from(state));
+                let data: GQLRepositoryData<GQLIssuesRepository> =
+                    self.query(client, &GraphQLRequest::new(ISSUES_QUERY, variables))?;
+                Ok(data.repository.issues)
+            }
+        }
+    }
+
+    /// Fetches up to `limit` incidents in the given state, newest first, and
+    /// completes their comments and labels beyond the first page. Issues not
+    /// matching the incident filter don't count towards the limit.
+    fn fetch_issues(
+        &self,
+        client: &Client,
+        state: &str,
+        limit: Option<usize>,
+    ) -> Result<Vec<Issue>, Error> {
+        let mut issues = Vec::new();
+        let mut cursor = None;
+        loop {
+            let page = self.fetch_issues_page(client, state, cursor)?;
+            for mut incident in page.nodes {
+                if limit.is_some_and(|limit| issues.len() >= limit) {
+                    return Ok(issues);
+                }
+                self.fetch_remaining_labels(client, &mut incident)?;
+                let comments_cursor = incident.comments.page_info.next_cursor();
+                let mut issue = Issue::from(incident);
+                if !issue.matches(&self.filter) {
                     continue;
                 }
-                component_names.push(String::from(
-                    component_name
-                        .name
-                        .strip_prefix(LABEL_COMPONENT_PREFIX)
-                        .unwrap(),
-                ));
+                self.fetch_remaining_comments(
+                    client,
+                    &issue.id,
+                    comments_cursor,
+                    &mut issue.comments,
+                )?;
+                issues.push(issue);
+            }
+            cursor = page.page_info.next_cursor();
+            if cursor.is_none() {
+                return Ok(issues);
             }
-            let incident = Incident::new_open(
-                component_names,
-                open_incident.id.clone(),
-                open_incident.created_at,
-                self.get_status(&open_incident.labels.nodes),
-                open_incident.title.clone(),
-                updates,
-            );
-            incidents.push(incident);
         }
-        incidents.sort();
-        incidents.reverse();
-        incidents
+    }
+
+    /// Appends the comments of issue `id` after `cursor` to `comments`.
+    fn fetch_remaining_comments(
+        &self,
+        client: &Client,
+        id: &str,
+        mut cursor: Option<String>,
+        comments: &mut Vec<Comment>,
+    ) -> Result<(), Error> {
+        while let Some(after) = cursor {
+            let variables = Self::node_variables(id, after);
+            let data: GQLNodeData<GQLIssueComments> = self.query(
+                client,
+                &GraphQLRequest::new(ISSUE_COMMENTS_QUERY, variables),
+            )?;
+            let page = data.node.comments;
+            comments.extend(page.nodes.into_iter().map(Comment::from));
+            cursor = page.page_info.next_cursor();
+        }
+        Ok(())
+    }
+
+    fn fetch_remaining_labels(
+        &self,
+        client: &Client,
+        incident: &mut GQLIncident,
+    ) -> Result<(), Error> {
+        while let Some(cursor) = incident.labels.page_info.next_cursor() {
+            let variables = Self::node_variables(&incident.id, cursor);
+            let data: GQLNodeData<GQLIssueLabels> =
+                self.query(client, &GraphQLRequest::new(ISSUE_LABELS_QUERY, variables))?;
+            let mut page = data.node.labels;
+            incident.labels.nodes.append(&mut page.nodes);
+            incident.labels.page_info = page.page_info;
+        }
+        Ok(())
+    }
+}
+
+impl IssueProvider for GitHubIssueProvider {
+    fn label_scheme(&self) -> &LabelScheme {
+        &self.label_scheme
+    }
+    fn get_open_incidents(&self) -> Vec<Incident> {
+        get_open_incidents(
+            &self.open_incidents,
+            &self.label_scheme,
+            &self.filter.updates,
+        )
     }
     fn get_closed_incidents(&self) -> Vec<Incident> {
-        let mut incidents = Vec::with_capacity(self.closed_incidents.len());
-        for closed_incident in self.closed_incidents.iter() {
-            let mut updates = Vec::with_capacity(closed_incident.comments.nodes.len() + 1);
-            updates.push(IncidentUpdate::new(
-                closed_incident.created_at,
-                closed_incident.body.clone(),
-            ));
-            for update in closed_incident.comments.nodes.iter() {
-                updates.push(IncidentUpdate::new(update.created_at, update.body.clone()));
+        get_closed_incidents(
+            &self.closed_incidents,
+            &self.label_scheme,
+            &self.filter.updates,
+        )
+    }
+
+    fn fetch_incidents(&mut self) -> Result<(), Error> {
+        let client = self.client()?;
+        let mut open_incidents = self.fetch_issues(&client, "OPEN", None)?;
+        let mut closed_incidents = self.fetch_issues(&client, "CLOSED", self.history_limit)?;
+        self.open_incidents.append(&mut open_incidents);
+        self.closed_incidents.append(&mut closed_incidents);
+        Ok(())
+    }
+
+    fn fetch_labels(&self) -> Result<Labels, Error> {
+        let client = self.client()?;
+        let mut names = Vec::new();
+        let mut cursor = None;
+        loop {
+            let variables = self.repository_variables(cursor);
+            let data: GQLRepositoryData<GQLLabelsRepository> =
+                self.query(&client, &GraphQLRequest::new(LABELS_QUERY, variables))?;
+            let page = data.repository.labels;
+            names.extend(page.nodes.into_iter().map(|label| label.name));
+            cursor = page.page_info.next_cursor();
+            if cursor.is_none() {
+                break;
             }
-            updates.sort();
-            updates.reverse();
-            let incident = Incident::new_closed(
-                closed_incident.closed_at,
-                closed_incident.id.clone(),
-                closed_incident.created_at,
-                self.get_status(&closed_incident.labels.nodes),
-                closed_incident.title.clone(),
-                updates,
-            );
-            incidents.push(incident);
         }
-        incidents.sort();
-        incidents
+        Ok(Labels::from_names(names, &self.label_scheme))
     }
 
-    fn fetch_incidents(&mut self) -> Result<(), Error> {
-        let github_token = env::var("GITHUB_TOKEN").unwrap();
-        let client = reqwest::blocking::Client::builder()
-            .user_agent("StatusPageRS/0.1.0")
-            .build()
-            .unwrap();
-        let mut variables = HashMap::with_capacity(2);
-        variables.insert("repository", self.repository.clone());
-        variables.insert("owner", self.owner.clone());
-        let params = GraphQLRequest::new(
-            r#"
-              query($repository: String!, $owner: String!){
-                repository(name: $repository, owner: $owner) {
-                  openIncidents: issues(first: 100, states: OPEN) {
-                    nodes {
-                      body
-                      closedAt
-                      comments(first: 100) {
-                        nodes {
-                          body
-                          createdAt
-                        }
-                      }
-                      createdAt
-                      id
-                      labels(first: 10) {
-                        nodes {
-                          name
-                        }
-                      }
-                      title
-                    }
-                  }
-                  closedIncidents: issues(first: 100, states: CLOSED) {
-                    nodes {
-                      body
-                      closedAt
-                      comments(first: 100) {
-                        nodes {
-                          body
-                          createdAt
-                        }
-                      }
-                      createdAt
-                      id
-                      labels(first: 10) {
-                        nodes {
-                          name
-                        }
-                      }
-                      title
-                    }
-                  }
-                }
-              }
-            "#,
-            variables,
-        );
-        let resp = client
-            .post("https://api.github.com/graphql")
+    fn apply_label_change(&self, change: &LabelChange) -> Result<(), Error> {
+        let github_token = get_token("GITHUB_TOKEN")?;
+        let client = self.client()?;
+        let request = match change {
+            LabelChange::Create {
+                name,
+                color,
+                description,
+            } => client.post(self.label_url(None)?).json(&serde_json::json!({
+                "name": name,
+                "color": color,
+                "description": description,
+            })),
+            LabelChange::Delete { name } => client.delete(self.label_url(Some(name))?),
+            LabelChange::Rename { name, new_name } => client
+                .patch(self.label_url(Some(name))?)
+                .json(&serde_json::json!({ "new_name": new_name })),
+        };
+        request
             .bearer_auth(&github_token)
-            .json(&params)
-            .send()
-            .unwrap();
-        let mut root: GQLRoot = resp.json().unwrap();
-        self.open_incidents
-            .append(&mut root.data.repository.open_incidents.nodes);
-        self.closed_incidents
-            .append(&mut root.data.repository.closed_incidents.nodes);
+            .header("Accept", "application/vnd.github+json")
+            .send()?
+            .error_for_status()?;
         Ok(())
     }
+}
 
-    fn fetch_labels(&self) -> Labels {
-        let github_token = env::var("GITHUB_TOKEN").unwrap();
-        let client = reqwest::blocking::Client::builder()
-            .user_agent("StatusPageRS/0.1.0")
-            .build()
-            .unwrap();
-        let mut variables = HashMap::with_capacity(2);
-        variables.insert("repository", self.repository.clone());
-        variables.insert("owner", self.owner.clone());
-        let params = GraphQLRequest::new(
-            r#"
-              query($repository: String!, $owner: String!){
-                repository(name: $repository, owner: $owner) {
-                  labels(first: 100) {
-                    nodes {
-                      name
-                    }
-                  }
-                }
-              }
-            "#,
-            variables,
-        );
-        let resp = client
-            .post("https://api.github.com/graphql")
-            .bearer_auth(&github_token)
-            .json(&params)
-            .send()
-            .unwrap();
-        let root: serde_json::Value = resp.json().unwrap();
-        let mut component_labels = HashSet::new();
-        let mut status_labels = HashSet::new();
-        if let Some(data) = root.get("data") {
-            if let Some(repository) = data.get("repository") {
-                if let Some(labels) = repository.get("labels") {
-                    if let Some(nodes) = labels.get("nodes") {
-                        if let Some(node) = nodes.as_array() {
-                            for label in node.iter() {
-                                let label =
-                                    String::from(label.get("name").unwrap().as_str().unwrap());
-                                if label.starts_with(LABEL_STATUS_PREFIX) {
-                                    status_labels.insert(label);
-                                } else if label.starts_with(LABEL_COMPONENT_PREFIX) {
-                                    component_labels.insert(String::from(label));
-                                }
-                            }
-                        }
-                    }
-                }
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use std::cell::RefCell;
+    use std::env;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::testing::{config, MockResponse, MockServer};
+
+    thread_local! {
+        static WAITS: RefCell<Vec<Duration>> = const { RefCell::new(Vec::new()) };
+    }
+
+    /// Records the wait instead of sleeping.
+    pub fn sleep(duration: Duration) {
+        WAITS.with(|waits| waits.borrow_mut().push(duration));
+    }
+
+    fn waits() -> Vec<Duration> {
+        WAITS.with(|waits| waits.borrow().clone())
+    }
+
+    /// Answers the first `failures` requests with `failure`, then with data.
+    fn start_server<F>(failures: usize, failure: F) -> MockServer
+    where
+        F: Fn() -> MockResponse + Send + 'static,
+    {
+        let calls = AtomicUsize::new(0);
+        MockServer::start(move |_| {
+            if calls.fetch_add(1, Ordering::SeqCst) < failures {
+                failure()
+            } else {
+                MockResponse::json(json!({"data": {"ok": true}}))
             }
-        }
-        Labels::new(component_labels, status_labels)
+        })
+    }
+
+    fn query(server: &MockServer) -> Result<serde_json::Value, Error> {
+        env::set_var("GITHUB_TOKEN", "test-token");
+        let config = config("");
+        let provider = GitHubIssueProvider::new(
+            String::from("owner"),
+            String::from("repository"),
+            format!("{}/graphql", server.url),
+            server.url.clone(),
+            None,
+            config.incidents.clone(),
+            LabelScheme::new(&config),
+        );
+        let client = provider.client()?;
+        provider.query(
+            &client,
+            &GraphQLRequest::new(ISSUE_LABELS_QUERY, HashMap::new()),
+        )
     }
 
-    fn get_expected_labels(&self, components: &HashMap<String, Component>) -> Labels {
-        let mut component_labels = HashSet::new();
-        for comp in components.keys() {
-            component_labels.insert(format!("{}{}", LABEL_COMPONENT_PREFIX, comp));
+    fn api_error(result: Result<serde_json::Value, Error>) -> String {
+        match result {
+            Err(Error::Api(msg)) => msg,
+            other => panic!("expected an API error, got {:?}", other),
         }
-        let mut status_labels = HashSet::new();
-        status_labels.insert(String::from(LABEL_STATUS_OPERATIONAL));
-        status_labels.insert(String::from(LABEL_STATUS_PARTIAL_OUTAGE));
-        status_labels.insert(String::from(LABEL_STATUS_MAJOR_OUTAGE));
-        Labels::new(component_labels, status_labels)
+    }
+
+    #[test]
+    fn formats_graphql_errors() {
+        let server = start_server(usize::MAX, || {
+            MockResponse::json(json!({"errors": [
+                {"type": "NOT_FOUND", "message": "Could not resolve to a Repository.", "path": ["repository", 0]},
+                {"message": "Something else failed."},
+            ]}))
+        });
+        assert_eq!(
+            api_error(query(&server)),
+            "GitHub GraphQL query failed: NOT_FOUND: Could not resolve to a Repository. \
+             (at repository.0); Something else failed."
+        );
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[test]
+    fn retries_rate_limited_query() {
+        let server = start_server(1, || {
+            MockResponse::json(json!({"errors": [
+                {"type": "RATE_LIMITED", "message": "API rate limit exceeded."},
+            ]}))
+        });
+        assert_eq!(query(&server).unwrap(), json!({"ok": true}));
+        assert_eq!(waits(), [RATE_LIMIT_WAIT]);
+    }
+
+    #[test]
+    fn waits_for_rate_limit_reset() {
+        let reset = Utc::now().timestamp() + 30;
+        let server = start_server(1, move || {
+            MockResponse::json(json!({"message": "API rate limit exceeded."}))
+                .status(403)
+                .header("X-RateLimit-Limit", "5000")
+                .header("X-RateLimit-Remaining", "0")
+                .header("X-RateLimit-Reset", &reset.to_string())
+        });
+        assert!(query(&server).is_ok());
+        let waits = waits();
+        assert_eq!(waits.len(), 1);
+        assert!(
+            (Duration::from_secs(29)..=Duration::from_secs(31)).contains(&waits[0]),
+            "{:?}",
+            waits
+        );
+    }
+
+    #[test]
+    fn waits_a_minute_for_secondary_rate_limit() {
+        let server = start_server(1, || {
+            MockResponse::json(json!({
+                "message": "You have exceeded a secondary rate limit. Please wait a few minutes before you try again."
+            }))
+            .status(403)
+            .header("X-RateLimit-Limit", "5000")
+            .header("X-RateLimit-Remaining", "4000")
+            .header("X-RateLimit-Reset", "0")
+        });
+        assert!(query(&server).is_ok());
+        assert_eq!(waits(), [RATE_LIMIT_WAIT]);
+    }
+
+    #[test]
+    fn fails_on_missing_permissions() {
+        let server = start_server(usize::MAX, || {
+            MockResponse::json(json!({"message": "Resource not accessible by integration"}))
+                .status(403)
+        });
+        assert!(api_error(query(&server)).contains("Resource not accessible"));
+        assert_eq!(server.requests().len(), 1);
+        assert!(waits().is_empty());
+    }
+
+    #[test]
+    fn gives_up_after_max_retries() {
+        let server = start_server(usize::MAX, || {
+            MockResponse::json(json!({"message": "Bad Gateway"})).status(502)
+        });
+        assert!(api_error(query(&server)).contains("giving up after 5 retries"));
+        assert_eq!(server.requests().len(), MAX_RETRIES as usize + 1);
+        let expected: Vec<Duration> = (0..MAX_RETRIES)
+            .map(|attempt| Duration::from_secs(1 << attempt))
+            .collect();
+        assert_eq!(waits(), expected);
+    }
+
+    #[test]
+    fn gives_up_when_wait_is_too_long() {
+        let server = start_server(usize::MAX, || {
+            MockResponse::json(json!({"message": "API rate limit exceeded."}))
+                .status(429)
+                .header("Retry-After", "3600")
+        });
+        assert!(api_error(query(&server)).contains("only possible in 3600 seconds"));
+        assert_eq!(server.requests().len(), 1);
+        assert!(waits().is_empty());
     }
 }
@@ -0,0 +1,400 @@
+use chrono::prelude::*;
+use reqwest::blocking::Client;
+use reqwest::Url;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use crate::config::IncidentFilter;
+use crate::labels::LabelChange;
+use crate::provider::{
+    get_closed_incidents, get_open_incidents, get_token, Comment, Issue, IssueProvider,
+    LabelScheme, Labels,
+};
+use crate::types::{Error, Incident};
+use crate::utils::{parse_datetime, parse_datetime_optional};
+
+#[derive(Debug, Deserialize)]
+struct GLLabel {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GLNote {
+    author: Option<GLUser>,
+    body: String,
+    #[serde(deserialize_with = "parse_datetime")]
+    created_at: DateTime<Utc>,
+    id: u64,
+    system: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct GLAwardEmoji {
+    name: String,
+}
+
+/// Maps an award emoji to the reaction names of the other backends.
+fn reaction_name(emoji: String) -> String {
+    match emoji.as_str() {
+        "thumbsup" => String::from("+1"),
+        "thumbsdown" => String::from("-1"),
+        "laughing" => String::from("laugh"),
+        "tada" => String::from("hooray"),
+        _ => emoji,
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct GLUser {
+    username: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GLIssue {
+    author: Option<GLUser>,
+    #[serde(deserialize_with = "parse_datetime_optional")]
+    closed_at: Option<DateTime<Utc>>,
+    #[serde(deserialize_with = "parse_datetime")]
+    created_at: DateTime<Utc>,
+    description: Option<String>,
+    iid: u64,
+    labels: Vec<String>,
+    title: String,
+}
+
+pub struct GitLabIssueProvider {
+    url: Url,
+    project: String,
+    filter: IncidentFilter,
+    open_incidents: Vec<Issue>,
+    closed_incidents: Vec<Issue>,
+    label_scheme: LabelScheme,
+}
+
+impl GitLabIssueProvider {
+    pub fn new(
+        url: String,
+        project: String,
+        filter: IncidentFilter,
+        label_scheme: LabelScheme,
+    ) -> Result<Self, Error> {
+        let url = match Url::parse(&url) {
+            Ok(url) if !url.cannot_be_a_base() => url,
+            _ => {
+                return Err(Error::Config(format!("Invalid GitLab URL {:?}.", url)));
+            }
+        };
+        Ok(GitLabIssueProvider {
+            url,
+            project,
+            filter,
+            open_incidents: Vec::new(),
+            closed_incidents: Vec::new(),
+            label_scheme,
+        })
+    }
+
+    fn client(&self) -> Result<Client, Error> {
+        Ok(Client::builder().user_agent("StatusPageRS/0.1.0").build()?)
+    }
+
+    /// Builds the URL `<url>/api/v4/projects/<project>/<path...>`, taking care
+    /// of encoding the `/` in the project path.
+    fn project_url(&self, path: &[&str]) -> Url {
+        let mut url = self.url.clone();
+        url.path_segments_mut()
+            .expect("URL is validated in new()")
+            .pop_if_empty()
+            .extend(&["api", "v4", "projects", &self.project])
+            .extend(path);
+        url
+    }
+
+    /// Fetches all pages of a list endpoint by following GitLab's
+    /// `X-Next-Page` header.
+    fn get_all<T: DeserializeOwned>(&self, client: &Client, url: Url) -> Result<Vec<T>, Error> {
+        let gitlab_token = get_token("GITLAB_TOKEN")?;
+        let mut items = Vec::new();
+        let mut page = String::from("1");
+        loop {
+            let resp = client
+                .get(url.clone())
+                .header("PRIVATE-TOKEN", &gitlab_token)
+                .query(&[("per_page", "100"), ("page", page.as_str())])
+                .send()?
+                .error_for_status()?;
+            let next_page = resp
+                .headers()
+                .get("x-next-page")
+                .and_then(|value| value.to_str().ok())
+                .map(String::from)
+                .unwrap_or_default();
+            let mut batch: Vec<T> = resp.json()?;
+            items.append(&mut batch);
+            if next_page.is_empty() {
+                break;
+            }
+            page = next_page;
+        }
+        Ok(items)
+    }
+
+    fn fetch_issues(&self, client: &Client, state: &str) -> Result<Vec<Issue>, Error> {
+        let mut url = self.project_url(&["issues"]);
+        url.query_pairs_mut().append_pair("state", state);
+        if let Some(label) = &self.filter.label {
+            url.query_pairs_mut().append_pair("labels", label);
+        }
+        let issues: Vec<GLIssue> = self.get_all(client, url)?;
+        let mut result = Vec::with_capacity(issues.len());
+        for issue in issues {
+            let mut issue = Issue {
+                author: issue.author.map(|author| author.username),
+                body: issue.description.unwrap_or_default(),
+                closed_at: issue.closed_at,
+                comments: Vec::new(),
+                created_at: issue.created_at,
+                id: issue.iid.to_string(),
+                labels: issue.labels,
+                maintenance: None,
+                title: issue.title,
+            };
+            if !issue.matches(&self.filter) {
+                continue;
+            }
+            let mut url = self.project_url(&["issues", &issue.id, "notes"]);
+            url.query_pairs_mut()
+                .append_pair("sort", "asc")
+                .append_pair("order_by", "created_at");
+            let notes: Vec<GLNote> = self.get_all(client, url)?;
+            for note in notes.into_iter().filter(|note| !note.system) {
+                // Award emoji need a request per note, skip them unless needed.
+                let mut reactions = Vec::new();
+                if !self.filter.updates.exclude_reactions.is_empty() {
+                    let url = self.project_url(&[
+                        "issues",
+                        &issue.id,
+                        "notes",
+                        &note.id.to_string(),
+                        "award_emoji",
+                    ]);
+                    let emoji: Vec<GLAwardEmoji> = self.get_all(client, url)?;
+                    reactions = emoji
+                        .into_iter()
+                        .map(|emoji| reaction_name(emoji.name))
+                        .collect();
+                }
+                issue.comments.push(Comment {
+                    author: note.author.map(|author| author.username),
+                    body: note.body,
+                    created_at: note.created_at,
+                    reactions,
+                });
+            }
+            result.push(issue);
+        }
+        Ok(result)
+    }
+}
+
+impl IssueProvider for GitLabIssueProvider {
+    fn label_scheme(&self) -> &LabelScheme {
+        &self.label_scheme
+    }
+    fn get_open_incidents(&self) -> Vec<Incident> {
+        get_open_incidents(
+            &self.open_incidents,
+            &self.label_scheme,
+            &self.filter.updates,
+        )
+    }
+    fn get_closed_incidents(&self) -> Vec<Incident> {
+        get_closed_incidents(
+            &self.closed_incidents,
+            &self.label_scheme,
+            &self.filter.updates,
+        )
+    }
+
+    fn fetch_incidents(&mut self) -> Result<(), Error> {
+        let client = self.client()?;
+        let mut open_incidents = self.fetch_issues(&client, "opened")?;
+        let mut closed_incidents = self.fetch_issues(&client, "closed")?;
+        self.open_incidents.append(&mut open_incidents);
+        self.closed_incidents.append(&mut closed_incidents);
+        Ok(())
+    }
+
+    fn fetch_labels(&self) -> Result<Labels, Error> {
+        let client = self.client()?;
+        let labels: Vec<GLLabel> = self.get_all(&client, self.project_url(&["labels"]))?;
+        Ok(Labels::from_names(
+            labels.into_iter().map(|label| label.name),
+            &self.label_scheme,
+        ))
+    }
+
+    fn apply_label_change(&self, change: &LabelChange) -> Result<(), Error> {
+        let gitlab_token = get_token("GITLAB_TOKEN")?;
+        let client = self.client()?;
+        let request = match change {
+            LabelChange::Create {
+                name,
+                color,
+                description,
+            } => client
+                .post(self.project_url(&["labels"]))
+                .json(&serde_json::json!({
+                    "name": name,
+                    "color": format!("#{}", color),
+                    "description": description,
+                })),
+            LabelChange::Delete { name } => client.delete(self.project_url(&["labels", name])),
+            LabelChange::Rename { name, new_name } => client
+                .put(self.project_url(&["labels", name]))
+                .json(&serde_json::json!({ "new_name": new_name })),
+        };
+        request
+            .header("PRIVATE-TOKEN", &gitlab_token)
+            .send()?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use std::env;
+
+    use super::*;
+    use crate::testing::{config, MockResponse, MockServer};
+
+    fn issue(iid: u64, labels: &[&str]) -> serde_json::Value {
+        json!({
+            "author": {"username": "alice"},
+            "closed_at": null,
+            "created_at": "2022-03-01T10:00:00Z",
+            "description": "We are investigating.",
+            "iid": iid,
+            "labels": labels,
+            "title": format!("Incident {}", iid),
+        })
+    }
+
+    fn note(id: u64, body: &str, system: bool) -> serde_json::Value {
+        json!({
+            "author": {"username": "alice"},
+            "body": body,
+            "created_at": format!("2022-03-01T11:00:{:02}Z", id),
+            "id": id,
+            "system": system,
+        })
+    }
+
+    /// A project with two open incidents on two pages and no closed ones.
+    fn start_server() -> MockServer {
+        MockServer::start(|request| {
+            let path = request.url.split('?').next().unwrap_or_default();
+            let page_2 = request.url.contains("page=2");
+            if path.ends_with("/issues") && request.url.contains("state=closed") {
+                MockResponse::json(json!([]))
+            } else if path.ends_with("/issues") && page_2 {
+                MockResponse::json(json!([issue(2, &["component/website"])]))
+                    .header("X-Next-Page", "")
+            } else if path.ends_with("/issues") {
+                MockResponse::json(json!([issue(
+                    1,
+                    &["component/database", "status::major-outage"]
+                )]))
+                .header("X-Next-Page", "2")
+            } else if path.ends_with("/notes") {
+                MockResponse::json(json!([
+                    note(1, "Identified the cause.", false),
+                    note(2, "added ~status::major-outage label", true),
+                ]))
+            } else {
+                MockResponse::json(json!({"message": "404 Not Found"})).status(404)
+            }
+        })
+    }
+
+    fn fetch(server: &MockServer) -> GitLabIssueProvider {
+        env::set_var("GITLAB_TOKEN", "test-token");
+        let config = config("");
+        let mut provider = GitLabIssueProvider::new(
+            server.url.clone(),
+            String::from("group/project"),
+            config.incidents.clone(),
+            LabelScheme::new(&config),
+        )
+        .unwrap();
+        provider.fetch_incidents().unwrap();
+        provider
+    }
+
+    #[test]
+    fn follows_next_page_header() {
+        let server = start_server();
+        let provider = fetch(&server);
+        let ids: Vec<String> = provider
+            .get_open_incidents()
+            .into_iter()
+            .map(|incident| incident.id)
+            .collect();
+        assert_eq!(ids, ["1", "2"]);
+        let pages: Vec<String> = server
+            .requests()
+            .into_iter()
+            .filter(|request| request.url.contains("state=opened"))
+            .map(|request| request.url)
+            .collect();
+        assert_eq!(pages.len(), 2);
+        assert!(pages[0].contains("page=1"));
+        assert!(pages[1].contains("page=2"));
+    }
+
+    #[test]
+    fn encodes_project_path() {
+        let server = start_server();
+        fetch(&server);
+        let requests = server.requests();
+        assert!(requests
+            .iter()
+            .all(|request| request.url.starts_with("/api/v4/projects/group%2Fproject/")));
+        assert!(requests.iter().any(|request| request
+            .url
+            .starts_with("/api/v4/projects/group%2Fproject/issues/1/notes?")));
+    }
+
+    #[test]
+    fn maps_labels_to_status_and_components() {
+        let server = start_server();
+        let incidents = fetch(&server).get_open_incidents();
+        assert_eq!(incidents[0].component_names, ["database"]);
+        assert_eq!(
+            incidents[0].severity.as_ref().map(|s| s.key.as_str()),
+            Some("major-outage")
+        );
+        assert_eq!(incidents[1].component_names, ["website"]);
+        assert!(incidents[1].severity.is_none());
+    }
+
+    #[test]
+    fn skips_system_notes() {
+        let server = start_server();
+        let incidents = fetch(&server).get_open_incidents();
+        let texts: Vec<&str> = incidents[0]
+            .updates
+            .iter()
+            .map(|update| update.text.trim())
+            .collect();
+        assert_eq!(
+            texts,
+            [
+                "<p>Identified the cause.</p>",
+                "<p>We are investigating.</p>"
+            ]
+        );
+    }
+}
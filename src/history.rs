@@ -0,0 +1,287 @@
+//! The status history of each component over the last [`HISTORY_DAYS`] days,
+//! derived from the incidents that affected it.
+//!
+//! An incident affects its components from when it was opened until it was
+//! closed, maintenance during its window. Each day gets the most severe
+//! status of these incidents. Incidents with a severity above the lowest
+//! level count as downtime for the uptime percentage, maintenance doesn't.
+
+use chrono::prelude::*;
+use chrono::Duration;
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::types::{ComponentStatus, Components, Incident};
+
+/// The number of days shown, including today.
+pub const HISTORY_DAYS: i64 = 90;
+
+#[derive(Debug, Serialize)]
+pub struct DayStatus {
+    pub date: NaiveDate,
+    /// The most severe status of the day.
+    pub status: ComponentStatus,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ComponentHistory {
+    /// Oldest first, the last day is today.
+    pub days: Vec<DayStatus>,
+    /// The percentage of time without downtime, from 0 to 100.
+    pub uptime: f64,
+}
+
+/// An incident while it affected a component, clipped to the history.
+struct Outage<'a> {
+    severity: &'a ComponentStatus,
+    maintenance: bool,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+}
+
+/// When the incident affected its components, `None` for maintenance that
+/// hasn't started yet.
+fn active_interval(
+    incident: &Incident,
+    now: DateTime<Utc>,
+) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    let (start, end) = match &incident.maintenance {
+        Some(window) => (window.start, window.end),
+        None => (incident.opened, incident.closed.unwrap_or(now)),
+    };
+    (start < now).then(|| (start, end.min(now)))
+}
+
+/// The total length of `intervals`, counting overlapping parts once.
+fn covered(mut intervals: Vec<(DateTime<Utc>, DateTime<Utc>)>) -> Duration {
+    intervals.sort();
+    let mut total = Duration::zero();
+    let mut current: Option<(DateTime<Utc>, DateTime<Utc>)> = None;
+    for (start, end) in intervals {
+        current = match current {
+            Some((current_start, current_end)) if start <= current_end => {
+                Some((current_start, current_end.max(end)))
+            }
+            Some((current_start, current_end)) => {
+                total += current_end - current_start;
+                Some((start, end))
+            }
+            None => Some((start, end)),
+        };
+    }
+    if let Some((start, end)) = current {
+        total += end - start;
+    }
+    total
+}
+
+fn get_component_history(
+    slug: &str,
+    incidents: &[&Incident],
+    default_status: &ComponentStatus,
+    now: DateTime<Utc>,
+) -> ComponentHistory {
+    let today = now.date_naive();
+    let first_day = today - Duration::days(HISTORY_DAYS - 1);
+    let window_start = first_day.and_time(NaiveTime::MIN).and_utc();
+
+    let outages: Vec<Outage> = incidents
+        .iter()
+        .filter(|incident| incident.affects(slug))
+        .filter_map(|incident| {
+            let severity = incident.severity.as_ref()?;
+            let (start, end) = active_interval(incident, now)?;
+            (end > window_start).then_some(Outage {
+                severity,
+                maintenance: incident.maintenance.is_some(),
+                start: start.max(window_start),
+                end,
+            })
+        })
+        .collect();
+
+    let days = first_day
+        .iter_days()
+        .take(HISTORY_DAYS as usize)
+        .map(|date| {
+            let day_start = date.and_time(NaiveTime::MIN).and_utc();
+            let day_end = day_start + Duration::days(1);
+            let status = outages
+                .iter()
+                .filter(|outage| outage.start < day_end && outage.end > day_start)
+                .map(|outage| outage.severity)
+                .max()
+                .filter(|severity| *severity > default_status)
+                .unwrap_or(default_status);
+            DayStatus {
+                date,
+                status: status.clone(),
+            }
+        })
+        .collect();
+
+    let downtime = covered(
+        outages
+            .iter()
+            .filter(|outage| !outage.maintenance && outage.severity > default_status)
+            .map(|outage| (outage.start, outage.end))
+            .collect(),
+    );
+    let total = now - window_start;
+    let uptime = if total > Duration::zero() {
+        100.0 * (1.0 - downtime.num_seconds() as f64 / total.num_seconds() as f64)
+    } else {
+        100.0
+    };
+    ComponentHistory { days, uptime }
+}
+
+/// The history of each component, keyed by its slug.
+pub fn get_history(
+    components: &Components,
+    incidents: &[&Incident],
+    default_status: &ComponentStatus,
+    now: DateTime<Utc>,
+) -> HashMap<String, ComponentHistory> {
+    components
+        .keys()
+        .map(|slug| {
+            (
+                slug.clone(),
+                get_component_history(slug, incidents, default_status, now),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::config;
+    use crate::types::{IncidentDetails, MaintenanceWindow};
+
+    fn at(day: u32, hour: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2022, 6, day, hour, 0, 0).unwrap()
+    }
+
+    /// Noon of the last day of the history.
+    fn now() -> DateTime<Utc> {
+        at(30, 12)
+    }
+
+    fn status(key: &str) -> ComponentStatus {
+        config("")
+            .statuses
+            .into_iter()
+            .find(|status| status.key == key)
+            .unwrap()
+    }
+
+    fn incident(
+        severity: &str,
+        opened: DateTime<Utc>,
+        closed: Option<DateTime<Utc>>,
+        maintenance: Option<MaintenanceWindow>,
+    ) -> Incident {
+        Incident::new_closed(
+            IncidentDetails {
+                component_names: vec![String::from("database")],
+                id: String::from("1"),
+                maintenance,
+                opened,
+                severity: Some(status(severity)),
+                title: String::from("Outage"),
+                updates: Vec::new(),
+            },
+            closed,
+        )
+    }
+
+    fn history(incidents: &[Incident]) -> ComponentHistory {
+        let incidents: Vec<&Incident> = incidents.iter().collect();
+        get_component_history("database", &incidents, &status("operational"), now())
+    }
+
+    /// The uptime with `downtime` seconds of downtime in the history up to
+    /// `now`, which covers 89.5 days.
+    fn uptime(downtime: i64) -> f64 {
+        100.0 * (1.0 - downtime as f64 / (89 * 86400 + 12 * 3600) as f64)
+    }
+
+    #[test]
+    fn covers_overlapping_intervals_once() {
+        let hours = |start, end| (at(1, start), at(1, end));
+        assert_eq!(
+            covered(vec![
+                hours(5, 6),
+                hours(0, 2),
+                hours(1, 3),
+                hours(3, 4),
+                hours(5, 5),
+            ]),
+            Duration::hours(5)
+        );
+        assert_eq!(covered(Vec::new()), Duration::zero());
+    }
+
+    #[test]
+    fn counts_overlapping_incidents_once() {
+        let history = history(&[
+            incident("major-outage", at(30, 0), Some(at(30, 6)), None),
+            incident("partial-outage", at(30, 3), Some(at(30, 9)), None),
+        ]);
+        assert_eq!(history.uptime, uptime(9 * 3600));
+        assert_eq!(history.days.len(), HISTORY_DAYS as usize);
+        let today = history.days.last().unwrap();
+        assert_eq!(today.date, now().date_naive());
+        assert_eq!(today.status.key, "major-outage");
+        assert_eq!(history.days[88].status.key, "operational");
+    }
+
+    #[test]
+    fn counts_open_incidents_until_now() {
+        let history = history(&[incident("major-outage", at(30, 6), None, None)]);
+        assert_eq!(history.uptime, uptime(6 * 3600));
+    }
+
+    #[test]
+    fn excludes_maintenance_from_downtime() {
+        let window = MaintenanceWindow {
+            start: at(29, 10),
+            end: at(29, 14),
+        };
+        let history = history(&[incident(
+            "under-maintenance",
+            at(28, 0),
+            Some(at(29, 14)),
+            Some(window),
+        )]);
+        assert_eq!(history.uptime, 100.0);
+        assert_eq!(history.days[88].status.key, "under-maintenance");
+        assert_eq!(history.days[87].status.key, "operational");
+    }
+
+    #[test]
+    fn clips_incidents_to_the_history() {
+        let first_day = now().date_naive() - Duration::days(HISTORY_DAYS - 1);
+        let window_start = first_day.and_time(NaiveTime::MIN).and_utc();
+        let history = history(&[
+            incident(
+                "major-outage",
+                Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).unwrap(),
+                Some(window_start + Duration::days(1)),
+                None,
+            ),
+            incident(
+                "major-outage",
+                Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).unwrap(),
+                Some(window_start),
+                None,
+            ),
+        ]);
+        assert_eq!(history.uptime, uptime(86400));
+        assert_eq!(history.days[0].date, first_day);
+        assert_eq!(history.days[0].status.key, "major-outage");
+        assert_eq!(history.days[1].status.key, "operational");
+    }
+}
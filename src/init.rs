@@ -0,0 +1,66 @@
+use std::fs::{create_dir_all, write};
+use std::path::Path;
+
+use crate::types::Error;
+use crate::utils::progress;
+
+const CONFIG: &str = include_str!("../config.toml.example");
+const EXAMPLE_STATIC_DIRECTORY: &str = r#"static_directory = "/usr/share/statuspagers/static""#;
+
+/// The files `init` creates, relative to the target directory.
+const FILES: &[(&str, &str)] = &[
+    ("templates/atom.xml", include_str!("../templates/atom.xml")),
+    (
+        "templates/base.html",
+        include_str!("../templates/base.html"),
+    ),
+    (
+        "templates/detail.html",
+        include_str!("../templates/detail.html"),
+    ),
+    (
+        "templates/index.html",
+        include_str!("../templates/index.html"),
+    ),
+    (
+        "templates/macros.html",
+        include_str!("../templates/macros.html"),
+    ),
+    ("templates/rss.xml", include_str!("../templates/rss.xml")),
+    (
+        "templates/stats.html",
+        include_str!("../templates/stats.html"),
+    ),
+    (
+        "static/tailwind.min.css",
+        include_str!("../dist/tailwind.min.css"),
+    ),
+];
+
+fn write_file(path: &Path, content: &str, force: bool) -> Result<(), Error> {
+    if path.exists() && !force {
+        return Err(Error::Config(format!(
+            "{} already exists, use --force to overwrite it.",
+            path.display()
+        )));
+    }
+    if let Some(parent) = path.parent() {
+        create_dir_all(parent).map_err(|e| Error::io(parent, e))?;
+    }
+    write(path, content).map_err(|e| Error::io(path, e))?;
+    progress!("Created {}", path.display());
+    Ok(())
+}
+
+/// Sets up a new status page in `directory` with the example configuration,
+/// the default templates and styles.
+pub fn init(directory: &Path, force: bool) -> Result<(), Error> {
+    let config = CONFIG.replace(EXAMPLE_STATIC_DIRECTORY, r#"static_directory = "./static""#);
+    write_file(&directory.join("config.toml"), &config, force)?;
+    for (path, content) in FILES {
+        write_file(&directory.join(path), content, force)?;
+    }
+    let output = directory.join("html");
+    create_dir_all(&output).map_err(|e| Error::io(&output, e))?;
+    Ok(())
+}
@@ -0,0 +1,363 @@
+use std::fmt;
+
+use crate::config::{LabelStyle, LabelsConfig};
+use crate::provider::{LabelScheme, Labels};
+use crate::types::Components;
+
+const DEFAULT_COMPONENT_COLOR: &str = "1d76db";
+const DEFAULT_STATUS_COLOR: &str = "ededed";
+
+/// A single modification of the labels in the backend.
+#[derive(Debug, PartialEq)]
+pub enum LabelChange {
+    Create {
+        name: String,
+        color: String,
+        description: String,
+    },
+    Delete {
+        name: String,
+    },
+    Rename {
+        name: String,
+        new_name: String,
+    },
+}
+
+impl fmt::Display for LabelChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LabelChange::Create {
+                name,
+                color,
+                description,
+            } => write!(f, "create {} (#{}, {:?})", name, color, description),
+            LabelChange::Delete { name } => write!(f, "delete {}", name),
+            LabelChange::Rename { name, new_name } => write!(f, "rename {} to {}", name, new_name),
+        }
+    }
+}
+
+/// The differences between the labels in the backend and the labels the
+/// configuration expects. Phase and maintenance labels are optional, incidents
+/// work without them.
+pub struct LabelDiff {
+    pub missing_components: Vec<String>,
+    pub missing_statuses: Vec<String>,
+    pub missing_optional: Vec<String>,
+    pub obsolete_components: Vec<String>,
+}
+
+impl LabelDiff {
+    pub fn new(existing: &Labels, expected: &Labels, scheme: &LabelScheme) -> Self {
+        let sorted = |labels: Vec<&String>| {
+            let mut labels: Vec<String> = labels.into_iter().cloned().collect();
+            labels.sort();
+            labels
+        };
+        LabelDiff {
+            missing_components: sorted(
+                expected
+                    .components
+                    .difference(&existing.components)
+                    .collect(),
+            ),
+            missing_statuses: sorted(
+                expected
+                    .statuses
+                    .difference(&existing.statuses)
+                    .filter(|label| !scheme.is_optional_label(label))
+                    .collect(),
+            ),
+            missing_optional: sorted(
+                expected
+                    .statuses
+                    .difference(&existing.statuses)
+                    .filter(|label| scheme.is_optional_label(label))
+                    .collect(),
+            ),
+            obsolete_components: sorted(
+                existing
+                    .components
+                    .difference(&expected.components)
+                    .collect(),
+            ),
+        }
+    }
+
+    pub fn print(&self) {
+        if !self.missing_components.is_empty() {
+            eprintln!("Missing component labels:");
+            for label in self.missing_components.iter() {
+                eprintln!("- {}", label);
+            }
+        }
+        if !self.obsolete_components.is_empty() {
+            eprintln!("Obsolete component labels:");
+            for label in self.obsolete_components.iter() {
+                eprintln!("- {}", label);
+            }
+        }
+        if !self.missing_statuses.is_empty() {
+            eprintln!("Missing status labels:");
+            for label in self.missing_statuses.iter() {
+                eprintln!("- {}", label);
+            }
+        }
+        if !self.missing_optional.is_empty() {
+            eprintln!("Missing optional phase and maintenance labels:");
+            for label in self.missing_optional.iter() {
+                eprintln!("- {}", label);
+            }
+        }
+    }
+
+    /// Explains each rename that [`LabelDiff::plan`] ignores because its old
+    /// label isn't obsolete or its new label isn't missing.
+    pub fn unmatched_renames(&self, renames: &[(String, String)]) -> Vec<String> {
+        let mut renamed_to = Vec::new();
+        let mut messages = Vec::new();
+        for (from, to) in renames.iter() {
+            let reason = if !self.obsolete_components.contains(from) {
+                format!("{} is not an obsolete component label", from)
+            } else if !self.missing_components.contains(to) || renamed_to.contains(&to) {
+                format!("{} is not a missing component label", to)
+            } else {
+                renamed_to.push(to);
+                continue;
+            };
+            messages.push(format!("Ignoring --rename {}={}: {}.", from, to, reason));
+        }
+        messages
+    }
+
+    /// Computes the changes needed to bring the backend in line with the
+    /// configuration. Obsolete component labels are renamed when they appear
+    /// in `renames`, deleted when `delete_obsolete` is set and left alone
+    /// otherwise. A rename replaces creating its target label. Status labels
+    /// default to the color of their status level.
+    pub fn plan(
+        &self,
+        config: &LabelsConfig,
+        components: &Components,
+        scheme: &LabelScheme,
+        renames: &[(String, String)],
+        delete_obsolete: bool,
+    ) -> Vec<LabelChange> {
+        let mut changes = Vec::new();
+        let mut renamed_to = Vec::new();
+        for name in self.obsolete_components.iter() {
+            match renames.iter().find(|(from, _)| from == name) {
+                Some((_, to))
+                    if self.missing_components.contains(to) && !renamed_to.contains(&to) =>
+                {
+                    renamed_to.push(to);
+                    changes.push(LabelChange::Rename {
+                        name: name.clone(),
+                        new_name: to.clone(),
+                    });
+                }
+                _ if delete_obsolete => changes.push(LabelChange::Delete { name: name.clone() }),
+                _ => {}
+            }
+        }
+        for name in self.missing_components.iter() {
+            if renamed_to.contains(&name) {
+                continue;
+            }
+            let slug = scheme.component_slug(name).unwrap_or(name);
+            let style = config.components.get(slug);
+            let display_name = components
+                .get(slug)
+                .map(|c| c.name.as_str())
+                .or_else(|| scheme.group_name(slug))
+                .unwrap_or(slug);
+            changes.push(LabelChange::Create {
+                name: name.clone(),
+                color: pick_color(style, Some(&config.component), DEFAULT_COMPONENT_COLOR),
+                description: pick_description(style, Some(&config.component))
+                    .unwrap_or_else(|| format!("Incidents affecting {}", display_name))
+                    .replace("{component}", display_name),
+            });
+        }
+        for name in self.missing_statuses.iter().chain(&self.missing_optional) {
+            let style = config.statuses.get(name);
+            changes.push(LabelChange::Create {
+                name: name.clone(),
+                color: pick_color(
+                    style,
+                    None,
+                    scheme.label_color(name).unwrap_or(DEFAULT_STATUS_COLOR),
+                ),
+                description: pick_description(style, None).unwrap_or_default(),
+            });
+        }
+        changes
+    }
+}
+
+fn pick_color(style: Option<&LabelStyle>, fallback: Option<&LabelStyle>, default: &str) -> String {
+    let color = style
+        .and_then(|s| s.color.as_deref())
+        .or_else(|| fallback.and_then(|s| s.color.as_deref()))
+        .unwrap_or(default);
+    String::from(color.trim_start_matches('#'))
+}
+
+fn pick_description(style: Option<&LabelStyle>, fallback: Option<&LabelStyle>) -> Option<String> {
+    style
+        .and_then(|s| s.description.clone())
+        .or_else(|| fallback.and_then(|s| s.description.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ComponentConfig;
+    use crate::testing::config;
+    use crate::types::Component;
+
+    fn diff(obsolete: &[&str], missing: &[&str], statuses: &[&str]) -> LabelDiff {
+        let labels = |names: &[&str]| names.iter().map(|name| String::from(*name)).collect();
+        LabelDiff {
+            missing_components: labels(missing),
+            missing_statuses: labels(statuses),
+            missing_optional: Vec::new(),
+            obsolete_components: labels(obsolete),
+        }
+    }
+
+    fn plan(
+        diff: &LabelDiff,
+        extra: &str,
+        renames: &[(&str, &str)],
+        delete_obsolete: bool,
+    ) -> Vec<LabelChange> {
+        let config = config(extra);
+        let scheme = LabelScheme::new(&config);
+        let mut components = Components::new();
+        for name in ["Website", "Database"] {
+            let component = ComponentConfig {
+                name: String::from(name),
+                ..Default::default()
+            };
+            components.insert(
+                component.slug(),
+                Component::new(&component, scheme.default_status()),
+            );
+        }
+        let renames: Vec<(String, String)> = renames
+            .iter()
+            .map(|(from, to)| (String::from(*from), String::from(*to)))
+            .collect();
+        diff.plan(
+            &config.labels,
+            &components,
+            &scheme,
+            &renames,
+            delete_obsolete,
+        )
+    }
+
+    fn create(name: &str, color: &str, description: &str) -> LabelChange {
+        LabelChange::Create {
+            name: String::from(name),
+            color: String::from(color),
+            description: String::from(description),
+        }
+    }
+
+    #[test]
+    fn renames_instead_of_creating() {
+        let diff = diff(&["component/db"], &["component/database"], &[]);
+        assert_eq!(
+            plan(&diff, "", &[("component/db", "component/database")], true),
+            [LabelChange::Rename {
+                name: String::from("component/db"),
+                new_name: String::from("component/database"),
+            }]
+        );
+    }
+
+    #[test]
+    fn deletes_obsolete_labels_only_when_asked() {
+        let diff = diff(&["component/old"], &[], &[]);
+        assert!(plan(&diff, "", &[], false).is_empty());
+        assert_eq!(
+            plan(&diff, "", &[], true),
+            [LabelChange::Delete {
+                name: String::from("component/old"),
+            }]
+        );
+    }
+
+    #[test]
+    fn reports_unmatched_renames() {
+        let diff = diff(&["component/db"], &["component/database"], &[]);
+        let renames = |pairs: &[(&str, &str)]| -> Vec<(String, String)> {
+            pairs
+                .iter()
+                .map(|(from, to)| (String::from(*from), String::from(*to)))
+                .collect()
+        };
+        assert!(diff
+            .unmatched_renames(&renames(&[("component/db", "component/database")]))
+            .is_empty());
+        assert_eq!(
+            diff.unmatched_renames(&renames(&[
+                ("component/web", "component/website"),
+                ("component/db", "component/dbs"),
+            ])),
+            [
+                "Ignoring --rename component/web=component/website: component/web is not an obsolete component label.",
+                "Ignoring --rename component/db=component/dbs: component/dbs is not a missing component label.",
+            ]
+        );
+    }
+
+    #[test]
+    fn creates_component_labels_with_defaults() {
+        let diff = diff(&[], &["component/database"], &[]);
+        assert_eq!(
+            plan(&diff, "", &[], false),
+            [create(
+                "component/database",
+                DEFAULT_COMPONENT_COLOR,
+                "Incidents affecting Database"
+            )]
+        );
+    }
+
+    #[test]
+    fn creates_component_labels_with_configured_styles() {
+        let diff = diff(&[], &["component/database", "component/website"], &[]);
+        let extra = "[labels.component]
+color = \"#111111\"
+description = \"About {component}\"
+[labels.components.website]
+color = \"222222\"
+";
+        assert_eq!(
+            plan(&diff, extra, &[], false),
+            [
+                create("component/database", "111111", "About Database"),
+                create("component/website", "222222", "About Website"),
+            ]
+        );
+    }
+
+    #[test]
+    fn creates_status_labels_in_status_color() {
+        let diff = diff(&[], &[], &["status::major-outage", "status::unknown"]);
+        let extra = "[labels.statuses.\"status::unknown\"]
+description = \"Not sure yet\"
+";
+        assert_eq!(
+            plan(&diff, extra, &[], false),
+            [
+                create("status::major-outage", "ef4444", ""),
+                create("status::unknown", DEFAULT_STATUS_COLOR, "Not sure yet"),
+            ]
+        );
+    }
+}
@@ -1,130 +1,489 @@
+use chrono::Utc;
+use clap::Parser;
 use slug::slugify;
 use std::collections::HashMap;
-use std::fs;
-use std::path::PathBuf;
+use std::env;
+use std::fs::create_dir_all;
+use std::path::{Path, PathBuf};
+use std::process;
 use tera::{Context, Tera};
-use toml;
 
+mod api;
+mod badges;
+mod cli;
 mod config;
+mod feed;
+mod files;
+mod forgejo;
 mod github;
+mod gitlab;
+mod history;
+mod init;
+mod labels;
 mod provider;
+mod server;
+mod stats;
+mod statuspage;
+#[cfg(test)]
+mod testing;
 mod types;
 mod utils;
+mod watch;
 mod writer;
 
-use config::{Backend, Config};
+use cli::{Cli, Command, ServeArgs, SyncLabelsArgs};
+use config::{Backend, ComponentConfig, Config};
+use files::FilesIssueProvider;
+use forgejo::ForgejoIssueProvider;
 use github::GitHubIssueProvider;
-use provider::IssueProvider;
-use types::{Component, Incident};
-use writer::{copy_dir, render};
+use gitlab::GitLabIssueProvider;
+use history::ComponentHistory;
+use labels::LabelDiff;
+use provider::{IssueProvider, LabelScheme};
+use server::LiveReload;
+use types::{Component, ComponentGroup, Components, Error, Incident, IncidentStatus};
+use utils::progress;
+use watch::Watcher;
+use writer::{add_default_templates, copy_dir, render};
 
 fn main() {
-    let config_string =
-        fs::read_to_string("config.toml").expect("Something went wrong reading the config file.");
+    let cli = Cli::parse();
+    utils::set_verbosity(cli.quiet, cli.verbose);
+    if let Err(e) = run(cli) {
+        eprintln!("{}", e);
+        process::exit(e.exit_code());
+    }
+}
+
+fn run(cli: Cli) -> Result<(), Error> {
+    match cli.command.unwrap_or(Command::Build) {
+        Command::Build => {
+            let config = load_config(&cli.config, cli.output.as_deref())?;
+            let mut issue_provider = create_provider(&config)?;
+            diff_labels(&config, issue_provider.as_ref())?.print();
+            issue_provider.fetch_incidents()?;
+            build(&config, issue_provider.as_ref())
+        }
+        Command::Check => {
+            let config = load_config(&cli.config, cli.output.as_deref())?;
+            check(&config)
+        }
+        Command::SyncLabels(args) => {
+            let config = load_config(&cli.config, cli.output.as_deref())?;
+            sync_labels(&config, &args)
+        }
+        Command::Serve(args) => serve(&cli.config, cli.output.as_deref(), &args),
+        Command::Watch => watch(&cli.config, cli.output.as_deref(), || {}),
+        Command::Init(args) => init::init(&args.directory, args.force),
+    }
+}
 
-    let config: Config = toml::from_str(&config_string).unwrap();
+fn load_config(path: &Path, output: Option<&Path>) -> Result<Config, Error> {
+    let mut config = Config::load(path)?;
+    if let Some(output) = output {
+        config.output_dir = output.to_path_buf();
+    }
+    Ok(config)
+}
+
+fn missing_section(section: &str) -> Error {
+    Error::Config(format!(
+        "The selected backend requires a [{}] section.",
+        section
+    ))
+}
 
-    let mut issue_provider = match config.backend {
-        Backend::GITHUB => match config.github {
-            Some(gh) => GitHubIssueProvider::new(gh.owner, gh.repository),
-            None => return,
+fn create_provider(config: &Config) -> Result<Box<dyn IssueProvider>, Error> {
+    if config.incidents.query.is_some() && !matches!(config.backend, Backend::GitHub) {
+        return Err(Error::Config(String::from(
+            "The incidents query is only supported by the github backend.",
+        )));
+    }
+    let label_scheme = LabelScheme::new(config);
+    Ok(match config.backend {
+        Backend::GitHub => match &config.github {
+            Some(gh) => Box::new(GitHubIssueProvider::new(
+                gh.owner.clone(),
+                gh.repository.clone(),
+                gh.graphql_url.clone(),
+                gh.rest_url.clone(),
+                gh.history_limit,
+                config.incidents.clone(),
+                label_scheme,
+            )),
+            None => return Err(missing_section("github")),
         },
-    };
+        Backend::GitLab => match &config.gitlab {
+            Some(gl) => Box::new(GitLabIssueProvider::new(
+                gl.url.clone(),
+                gl.project.clone(),
+                config.incidents.clone(),
+                label_scheme,
+            )?),
+            None => return Err(missing_section("gitlab")),
+        },
+        Backend::Forgejo => match &config.forgejo {
+            Some(fj) => Box::new(ForgejoIssueProvider::new(
+                fj.url.clone(),
+                fj.owner.clone(),
+                fj.repository.clone(),
+                config.incidents.clone(),
+                label_scheme,
+            )?),
+            None => return Err(missing_section("forgejo")),
+        },
+        Backend::Files => match &config.files {
+            Some(files) => Box::new(FilesIssueProvider::new(
+                files.directory.clone(),
+                config.incidents.clone(),
+                label_scheme,
+            )),
+            None => return Err(missing_section("files")),
+        },
+    })
+}
 
-    let mut components = HashMap::with_capacity(config.components.len());
+/// All components, including those of the groups, in their default status.
+/// Components with an `order` come first, sorted by it, the others keep the
+/// configured order.
+fn get_components(config: &Config, scheme: &LabelScheme) -> Components {
+    let default_status = |comp: &ComponentConfig| {
+        comp.status
+            .as_ref()
+            .and_then(|key| config.statuses.iter().find(|status| &status.key == key))
+            .cloned()
+            .unwrap_or_else(|| scheme.default_status())
+    };
+    let mut components = Components::with_capacity(config.components.len());
     for comp in config.components.iter() {
-        components.insert(slugify(comp), Component::new(comp.to_string()));
-    }
-
-    let existing_labels = issue_provider.fetch_labels();
-    let expected_labels = issue_provider.get_expected_labels(&components);
-    let missing_component_labels = expected_labels
-        .components
-        .difference(&existing_labels.components)
-        .collect::<Vec<&String>>();
-    let missing_status_labels = expected_labels
-        .statuses
-        .difference(&existing_labels.statuses)
-        .collect::<Vec<&String>>();
-    let obsolet_component_labels = existing_labels
-        .components
-        .difference(&expected_labels.components)
-        .collect::<Vec<&String>>();
-    if missing_component_labels.len() > 0 {
-        eprintln!("Missing component labels:");
-        for label in missing_component_labels {
-            eprintln!("- {}", label);
-        }
+        components.insert(comp.slug(), Component::new(comp, default_status(comp)));
     }
-    if obsolet_component_labels.len() > 0 {
-        eprintln!("Obsolet component labels:");
-        for label in obsolet_component_labels {
-            eprintln!("- {}", label);
+    for group in config.component_groups.iter() {
+        for comp in group.components.iter() {
+            let mut component = Component::new(comp, default_status(comp));
+            component.group = Some(slugify(&group.name));
+            components.insert(comp.slug(), component);
         }
     }
-    if missing_status_labels.len() > 0 {
-        eprintln!("Missing status labels:");
-        for label in missing_status_labels {
-            eprintln!("- {}", label);
+    components.sort_by_cached_key(|_, component| (component.order.is_none(), component.order));
+    components
+}
+
+/// The groups in the configured order, in the most severe status of their
+/// public components. Their components are in the order of `components`.
+fn get_component_groups(
+    config: &Config,
+    components: &Components,
+    scheme: &LabelScheme,
+) -> Vec<ComponentGroup> {
+    config
+        .component_groups
+        .iter()
+        .map(|group| {
+            let slug = slugify(&group.name);
+            let slugs: Vec<String> = components
+                .iter()
+                .filter(|(_, component)| component.group.as_ref() == Some(&slug))
+                .map(|(slug, _)| slug.clone())
+                .collect();
+            ComponentGroup {
+                slug,
+                name: group.name.clone(),
+                status: slugs
+                    .iter()
+                    .filter_map(|slug| components.get(slug))
+                    .filter(|component| !component.hidden)
+                    .map(|component| &component.status)
+                    .max()
+                    .cloned()
+                    .unwrap_or_else(|| scheme.default_status()),
+                components: slugs,
+                collapsed: group.collapsed,
+            }
+        })
+        .collect()
+}
+
+fn diff_labels(config: &Config, issue_provider: &dyn IssueProvider) -> Result<LabelDiff, Error> {
+    let existing_labels = issue_provider.fetch_labels()?;
+    let expected_labels =
+        issue_provider.get_expected_labels(&get_components(config, issue_provider.label_scheme()));
+    Ok(LabelDiff::new(
+        &existing_labels,
+        &expected_labels,
+        issue_provider.label_scheme(),
+    ))
+}
+
+fn check(config: &Config) -> Result<(), Error> {
+    Tera::new(&config.template_dir.join("**").to_string_lossy())?;
+    let issue_provider = create_provider(config)?;
+    let label_diff = diff_labels(config, issue_provider.as_ref())?;
+    label_diff.print();
+    let missing = label_diff.missing_components.len() + label_diff.missing_statuses.len();
+    if missing > 0 {
+        return Err(Error::Config(format!(
+            "{} label(s) are missing, run `statuspagers sync-labels` to create them.",
+            missing
+        )));
+    }
+    progress!("Configuration and labels are fine.");
+    Ok(())
+}
+
+fn sync_labels(config: &Config, args: &SyncLabelsArgs) -> Result<(), Error> {
+    let issue_provider = create_provider(config)?;
+    let label_diff = diff_labels(config, issue_provider.as_ref())?;
+    for message in label_diff.unmatched_renames(&args.rename) {
+        eprintln!("{}", message);
+    }
+    let changes = label_diff.plan(
+        &config.labels,
+        &get_components(config, issue_provider.label_scheme()),
+        issue_provider.label_scheme(),
+        &args.rename,
+        args.delete_obsolete,
+    );
+    if changes.is_empty() {
+        progress!("All labels are up to date.");
+    }
+    for change in changes.iter() {
+        if args.dry_run {
+            println!("Would {}", change);
+        } else {
+            progress!("Applying: {} ...", change);
+            issue_provider.apply_label_change(change)?;
         }
     }
+    Ok(())
+}
+
+/// Serves the status page from a new temporary directory, or `output` if
+/// given, and rebuilds it on changes like [`watch`]. Open pages reload after
+/// every rebuild. The temporary directory is removed on Ctrl+C.
+fn serve(config_path: &Path, output: Option<&Path>, args: &ServeArgs) -> Result<(), Error> {
+    let mut temp_dir = None;
+    let output = match output {
+        Some(output) => output.to_path_buf(),
+        None => {
+            let dir = tempfile::Builder::new()
+                .prefix("statuspagers-serve-")
+                .tempdir()
+                .map_err(|e| Error::io(&env::temp_dir(), e))?;
+            let output = dir.path().to_path_buf();
+            temp_dir = Some(dir);
+            output
+        }
+    };
+    let live_reload = LiveReload::default();
+    server::spawn(output.clone(), &args.bind, args.port, live_reload.clone())?;
+    let result = watch(config_path, Some(&output), || live_reload.notify());
+    drop(temp_dir);
+    result
+}
 
-    let tera = match Tera::new(config.template_dir.to_str().unwrap()) {
-        Ok(t) => t,
-        Err(e) => {
-            println!("Parsing error(s): {}", e);
-            ::std::process::exit(1);
+/// Rebuilds the status page whenever something it is built from changes.
+/// Template and static file changes reuse the fetched incidents, changes to
+/// the configuration (or the incident files) fetch everything again.
+/// `on_rebuild` is called after every successful rebuild. Returns once
+/// SIGINT or SIGTERM arrives.
+fn watch(config_path: &Path, output: Option<&Path>, on_rebuild: impl Fn()) -> Result<(), Error> {
+    let signals = watch::Signals::register()?;
+    let mut config = load_config(config_path, output)?;
+    let mut issue_provider = create_provider(&config)?;
+    issue_provider.fetch_incidents()?;
+    build(&config, issue_provider.as_ref())?;
+
+    let source_paths =
+        |config: &Config| vec![config.template_dir.clone(), config.static_dir.clone()];
+    let data_paths = |config: &Config| {
+        let mut paths = vec![config_path.to_path_buf()];
+        if let Some(files) = &config.files {
+            paths.push(files.directory.clone());
         }
+        paths
     };
+    let mut sources = Watcher::new(source_paths(&config));
+    let mut data = Watcher::new(data_paths(&config));
+    progress!("Watching for changes, press Ctrl+C to stop.");
+    while signals.wait() {
+        let result = if data.changed() {
+            progress!("Configuration changed, rebuilding ...");
+            load_config(config_path, output).and_then(|new_config| {
+                let mut new_provider = create_provider(&new_config)?;
+                new_provider.fetch_incidents()?;
+                config = new_config;
+                issue_provider = new_provider;
+                sources = Watcher::new(source_paths(&config));
+                data = Watcher::new(data_paths(&config));
+                build(&config, issue_provider.as_ref())
+            })
+        } else if sources.changed() {
+            progress!("Templates or static files changed, rebuilding ...");
+            build(&config, issue_provider.as_ref())
+        } else {
+            continue;
+        };
+        match result {
+            Ok(()) => on_rebuild(),
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+    Ok(())
+}
+
+/// Renders the status page from the incidents the provider has fetched.
+fn build(config: &Config, issue_provider: &dyn IssueProvider) -> Result<(), Error> {
+    let mut tera = Tera::new(&config.template_dir.join("**").to_string_lossy())?;
+    add_default_templates(&mut tera, writer::TEMPLATES)?;
+    if config.feeds.enabled {
+        add_default_templates(&mut tera, feed::TEMPLATES)?;
+    }
+    if config.stats.enabled {
+        add_default_templates(&mut tera, stats::TEMPLATES)?;
+    }
+    create_dir_all(&config.output_dir).map_err(|e| Error::io(&config.output_dir, e))?;
 
-    issue_provider.fetch_incidents().unwrap();
+    let now = Utc::now();
+    let mut open_incidents = Vec::new();
+    let mut upcoming_maintenance = Vec::new();
+    let mut closed_incidents = issue_provider.get_closed_incidents();
+    for mut incident in issue_provider.get_open_incidents() {
+        incident.schedule(now);
+        match incident.status {
+            IncidentStatus::Open => open_incidents.push(incident),
+            IncidentStatus::Scheduled => upcoming_maintenance.push(incident),
+            IncidentStatus::Closed => closed_incidents.push(incident),
+        }
+    }
+    upcoming_maintenance.sort_by_key(|incident| incident.maintenance.as_ref().map(|w| w.start));
+    closed_incidents.sort();
 
-    let open_incidents = issue_provider.get_open_incidents();
+    let mut components = get_components(config, issue_provider.label_scheme());
     for incident in open_incidents.iter() {
         incident.update_components(&mut components);
     }
-    let closed_incidents = issue_provider.get_closed_incidents();
+    for incident in open_incidents
+        .iter_mut()
+        .chain(upcoming_maintenance.iter_mut())
+        .chain(closed_incidents.iter_mut())
+    {
+        incident.hide_components(&components);
+    }
+
+    // Shared by all pages, base.html derives the status colors from it.
+    let mut base = Context::new();
+    base.insert("components", &components);
+    let component_groups = get_component_groups(config, &components, issue_provider.label_scheme());
+    base.insert("component_groups", &component_groups);
+    base.insert("statuses", &config.statuses);
+    base.insert("feeds", &config.feeds.enabled);
+    base.insert("stats_page", &config.stats.enabled);
+    if let Some(base_url) = &config.base_url {
+        base.insert("base_url", base_url);
+    }
+
+    let incidents: Vec<&Incident> = open_incidents
+        .iter()
+        .chain(upcoming_maintenance.iter())
+        .chain(closed_incidents.iter())
+        .collect();
+    let default_status = issue_provider.label_scheme().default_status();
+    let history = history::get_history(&components, &incidents, &default_status, now);
 
     render_index(
         &tera,
-        &components,
+        &base,
         &open_incidents,
+        &upcoming_maintenance,
         &closed_incidents,
+        &history,
         config.output_dir.join("index.html"),
-    );
-    for incident in open_incidents.iter() {
+    )?;
+    for incident in incidents.iter() {
         render_incident(
             &tera,
-            &incident,
+            &base,
+            incident,
             config.output_dir.join(format!("{}.html", incident.id)),
-        );
+        )?;
     }
-    for incident in closed_incidents.iter() {
-        render_incident(
+    api::write_api(
+        &components,
+        &incidents,
+        &default_status,
+        now,
+        &config.output_dir,
+    )?;
+    if config.badges.enabled {
+        badges::write_badges(
+            &components,
+            types::overall_status(&components, &default_status),
+            config.badges.shields,
+            &config.output_dir,
+        )?;
+    }
+    if let Some(settings) = &config.statuspage {
+        statuspage::write_statuspage(
+            config,
+            settings,
+            &components,
+            &component_groups,
+            &incidents,
+            &default_status,
+            now,
+        )?;
+    }
+    if config.stats.enabled {
+        let stats = stats::get_statistics(&components, &config.statuses, &incidents, now);
+        let mut ctx = base.clone();
+        ctx.insert("stats", &stats);
+        render(
             &tera,
-            &incident,
-            config.output_dir.join(format!("{}.html", incident.id)),
-        );
+            &ctx,
+            "stats.html",
+            config.output_dir.join("stats.html"),
+        )?;
+        stats::write_exports(&stats, &incidents, &config.output_dir)?;
     }
-    copy_dir(&config.static_dir, &config.output_dir.join("static")).unwrap();
+    if config.feeds.enabled {
+        feed::render_feeds(
+            &tera,
+            &base,
+            &incidents,
+            &components,
+            config.feeds.updates,
+            &config.output_dir,
+        )?;
+    }
+    copy_dir(&config.static_dir, config.output_dir.join("static"))
 }
 
 fn render_index(
     tera: &Tera,
-    components: &HashMap<String, Component>,
-    open: &Vec<Incident>,
-    closed: &Vec<Incident>,
+    base: &Context,
+    open: &[Incident],
+    upcoming: &[Incident],
+    closed: &[Incident],
+    history: &HashMap<String, ComponentHistory>,
     output: PathBuf,
-) {
-    let mut ctx = Context::new();
-    ctx.insert("components", &components);
+) -> Result<(), Error> {
+    let mut ctx = base.clone();
     ctx.insert("open_incidents", &open);
+    ctx.insert("upcoming_maintenance", &upcoming);
     ctx.insert("closed_incidents", &closed);
-    render(&tera, &ctx, "index.html", output);
+    ctx.insert("history", history);
+    ctx.insert("history_days", &history::HISTORY_DAYS);
+    render(tera, &ctx, "index.html", output)
 }
 
-fn render_incident(tera: &Tera, incident: &Incident, output: PathBuf) {
-    let mut ctx = Context::new();
+fn render_incident(
+    tera: &Tera,
+    base: &Context,
+    incident: &Incident,
+    output: PathBuf,
+) -> Result<(), Error> {
+    let mut ctx = base.clone();
     ctx.insert("incident", &incident);
-    render(&tera, &ctx, "detail.html", output);
+    render(tera, &ctx, "detail.html", output)
 }
@@ -1,14 +1,211 @@
+use chrono::prelude::*;
 use serde::Serialize;
+use slug::slugify;
 use std::collections::{HashMap, HashSet};
+use std::env;
 
-use crate::types::{Component, Error, Incident};
+use crate::config::{Config, IncidentFilter, UpdateFilter, MAINTENANCE_STATUS};
+use crate::labels::LabelChange;
+use crate::types::{
+    ComponentStatus, Components, Error, Incident, IncidentDetails, IncidentPhase, IncidentUpdate,
+    MaintenanceWindow,
+};
 
 pub trait IssueProvider {
+    fn label_scheme(&self) -> &LabelScheme;
     fn get_open_incidents(&self) -> Vec<Incident>;
     fn get_closed_incidents(&self) -> Vec<Incident>;
     fn fetch_incidents(&mut self) -> Result<(), Error>;
-    fn fetch_labels(&self) -> Labels;
-    fn get_expected_labels(&self, components: &HashMap<String, Component>) -> Labels;
+    fn fetch_labels(&self) -> Result<Labels, Error>;
+    fn apply_label_change(&self, change: &LabelChange) -> Result<(), Error>;
+    fn get_expected_labels(&self, components: &Components) -> Labels {
+        let scheme = self.label_scheme();
+        let mut component_labels = HashSet::new();
+        for comp in components.keys().chain(scheme.groups.keys()) {
+            component_labels.insert(scheme.component_label(comp));
+        }
+        let mut status_labels = HashSet::new();
+        for status in scheme.statuses.iter() {
+            status_labels.insert(scheme.status_label(status));
+        }
+        for phase in IncidentPhase::ALL {
+            status_labels.insert(scheme.phase_label(phase));
+        }
+        status_labels.insert(scheme.maintenance_label.clone());
+        Labels::new(component_labels, status_labels)
+    }
+}
+
+/// Maps the labels in the backend to components and the status levels of the
+/// configuration, following the naming set up in `[labels]`.
+#[derive(Clone, Debug)]
+pub struct LabelScheme {
+    component_prefix: String,
+    maintenance_label: String,
+    phase_prefix: String,
+    status_prefix: String,
+    statuses: Vec<ComponentStatus>,
+    /// The groups by their slug, with their name and component slugs.
+    groups: HashMap<String, (String, Vec<String>)>,
+    /// The slugs of components with a configured slug, by their derived one.
+    slugs: HashMap<String, String>,
+}
+
+impl LabelScheme {
+    pub fn new(config: &Config) -> Self {
+        LabelScheme {
+            component_prefix: config.labels.component_prefix.clone(),
+            maintenance_label: config.labels.maintenance.clone(),
+            phase_prefix: config.labels.phase_prefix.clone(),
+            status_prefix: config.labels.status_prefix.clone(),
+            statuses: config.statuses.clone(),
+            groups: config
+                .component_groups
+                .iter()
+                .map(|group| {
+                    (
+                        slugify(&group.name),
+                        (
+                            group.name.clone(),
+                            group.components.iter().map(|c| c.slug()).collect(),
+                        ),
+                    )
+                })
+                .collect(),
+            slugs: config
+                .components
+                .iter()
+                .chain(
+                    config
+                        .component_groups
+                        .iter()
+                        .flat_map(|g| g.components.iter()),
+                )
+                .filter_map(|component| {
+                    let slug = component.slug.clone()?;
+                    Some((slugify(&component.name), slug))
+                })
+                .collect(),
+        }
+    }
+
+    /// The slug of a component given by its name, taking configured slugs
+    /// into account.
+    pub fn slug_for_name(&self, name: &str) -> String {
+        let slug = slugify(name);
+        self.slugs.get(&slug).cloned().unwrap_or(slug)
+    }
+
+    pub fn component_label(&self, slug: &str) -> String {
+        format!("{}{}", self.component_prefix, slug)
+    }
+
+    /// The component slug of a component label.
+    pub fn component_slug<'a>(&self, label: &'a str) -> Option<&'a str> {
+        label.strip_prefix(self.component_prefix.as_str())
+    }
+
+    /// The name of the group with the slug.
+    pub fn group_name(&self, slug: &str) -> Option<&str> {
+        self.groups.get(slug).map(|(name, _)| name.as_str())
+    }
+
+    /// The slugs of the components a component label stands for: all
+    /// components of a group, or the labeled component itself.
+    fn label_components<'a>(&'a self, label: &'a str) -> Vec<&'a str> {
+        let Some(slug) = self.component_slug(label) else {
+            return Vec::new();
+        };
+        match self.groups.get(slug) {
+            Some((_, components)) => components.iter().map(String::as_str).collect(),
+            None => vec![slug],
+        }
+    }
+
+    pub fn maintenance_label(&self) -> &str {
+        &self.maintenance_label
+    }
+
+    pub fn phase_label(&self, phase: IncidentPhase) -> String {
+        format!("{}{}", self.phase_prefix, phase.key())
+    }
+
+    /// Whether the label is a phase or the maintenance label, which aren't
+    /// required to exist in the backend.
+    pub fn is_optional_label(&self, label: &str) -> bool {
+        label == self.maintenance_label || self.find_phase(label).is_some()
+    }
+
+    fn find_phase(&self, label: &str) -> Option<IncidentPhase> {
+        label
+            .strip_prefix(self.phase_prefix.as_str())
+            .and_then(IncidentPhase::from_key)
+    }
+
+    /// The label of a status level, either configured for the level or the
+    /// status prefix followed by its key.
+    pub fn status_label(&self, status: &ComponentStatus) -> String {
+        match &status.label {
+            Some(label) => label.clone(),
+            None => format!("{}{}", self.status_prefix, status.key),
+        }
+    }
+
+    /// The label for the status level with the given key, `None` if no level
+    /// has that key.
+    pub fn status_label_for_key(&self, key: &str) -> Option<String> {
+        self.statuses
+            .iter()
+            .find(|status| status.key == key)
+            .map(|status| self.status_label(status))
+    }
+
+    /// The keys of the status levels, in the configured order.
+    pub fn status_keys(&self) -> Vec<&str> {
+        self.statuses
+            .iter()
+            .map(|status| status.key.as_str())
+            .collect()
+    }
+
+    fn find_status(&self, label: &str) -> Option<&ComponentStatus> {
+        self.statuses
+            .iter()
+            .find(|status| self.status_label(status) == label)
+    }
+
+    fn is_status_label(&self, label: &str) -> bool {
+        label.starts_with(self.status_prefix.as_str())
+            || label.starts_with(self.phase_prefix.as_str())
+            || label == self.maintenance_label
+            || self.find_status(label).is_some()
+    }
+
+    fn maintenance_status(&self) -> Option<&ComponentStatus> {
+        self.statuses
+            .iter()
+            .find(|status| status.key == MAINTENANCE_STATUS)
+    }
+
+    /// The status of components without open incidents, the lowest ranked
+    /// level.
+    pub fn default_status(&self) -> ComponentStatus {
+        self.statuses
+            .iter()
+            .min()
+            .cloned()
+            .expect("Config::load requires at least one status")
+    }
+
+    /// The page color of the status level a label stands for, if any.
+    pub fn label_color(&self, label: &str) -> Option<&str> {
+        let status = if label == self.maintenance_label {
+            self.maintenance_status()
+        } else {
+            self.find_status(label)
+        };
+        status.map(|status| status.color.as_str())
+    }
 }
 
 #[derive(Debug)]
@@ -24,6 +221,27 @@ impl Labels {
             statuses,
         }
     }
+
+    /// Sorts the given label names into component and status labels and drops
+    /// everything else. Phase and maintenance labels count as status labels.
+    pub fn from_names<I: IntoIterator<Item = String>>(names: I, scheme: &LabelScheme) -> Self {
+        let mut component_labels = HashSet::new();
+        let mut status_labels = HashSet::new();
+        for label in names {
+            if scheme.is_status_label(&label) {
+                status_labels.insert(label);
+            } else if scheme.component_slug(&label).is_some() {
+                component_labels.insert(label);
+            }
+        }
+        Labels::new(component_labels, status_labels)
+    }
+}
+
+/// Reads the API token for a backend from the environment.
+pub fn get_token(variable: &str) -> Result<String, Error> {
+    env::var(variable)
+        .map_err(|_| Error::Config(format!("The environment variable {} is not set.", variable)))
 }
 
 #[derive(Debug, Serialize)]
@@ -37,3 +255,361 @@ impl GraphQLRequest {
         GraphQLRequest { query, variables }
     }
 }
+
+/// A comment on an [`Issue`], as fetched from any backend.
+#[derive(Debug)]
+pub struct Comment {
+    /// The login of the user who wrote the comment, if known.
+    pub author: Option<String>,
+    pub body: String,
+    pub created_at: DateTime<Utc>,
+    /// The reactions on the comment, named like `+1`, `-1`, `laugh`,
+    /// `hooray`, `confused`, `heart`, `rocket` or `eyes`. Backends that need
+    /// a request per comment only fetch them when reactions are excluded.
+    pub reactions: Vec<String>,
+}
+
+impl Comment {
+    /// The text to publish as an update, or `None` if the `[incidents.updates]`
+    /// configuration keeps the comment internal.
+    fn published_body(&self, filter: &UpdateFilter) -> Option<&str> {
+        if !filter.authors.is_empty() {
+            let allowed = self.author.as_ref().is_some_and(|author| {
+                filter
+                    .authors
+                    .iter()
+                    .any(|allowed| allowed.eq_ignore_ascii_case(author))
+            });
+            if !allowed {
+                return None;
+            }
+        }
+        if let Some(hidden) = &filter.hidden_marker {
+            if self.body.contains(hidden.as_str()) {
+                return None;
+            }
+        }
+        if self.reactions.iter().any(|reaction| {
+            filter
+                .exclude_reactions
+                .iter()
+                .any(|excluded| excluded.eq_ignore_ascii_case(reaction))
+        }) {
+            return None;
+        }
+        match &filter.marker {
+            Some(marker) => self
+                .body
+                .trim_start()
+                .strip_prefix(marker.as_str())
+                .map(str::trim_start),
+            None => Some(&self.body),
+        }
+    }
+}
+
+/// The backend agnostic representation of an issue that providers turn into
+/// an [`Incident`].
+#[derive(Debug)]
+pub struct Issue {
+    /// The login of the user who opened the issue, if known.
+    pub author: Option<String>,
+    pub body: String,
+    pub closed_at: Option<DateTime<Utc>>,
+    pub comments: Vec<Comment>,
+    pub created_at: DateTime<Utc>,
+    pub id: String,
+    pub labels: Vec<String>,
+    /// The maintenance window, for backends that store it outside the body.
+    pub maintenance: Option<MaintenanceWindow>,
+    pub title: String,
+}
+
+/// Parses a time given in a maintenance announcement, either as RFC 3339 or
+/// as `YYYY-MM-DD HH:MM` in UTC.
+fn parse_maintenance_time(value: &str) -> Option<DateTime<Utc>> {
+    let value = value.trim();
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(value) {
+        return Some(datetime.with_timezone(&Utc));
+    }
+    NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M")
+        .ok()
+        .map(|datetime| datetime.and_utc())
+}
+
+/// Finds the `Start:` and `End:` lines of a maintenance announcement. The
+/// keys are case insensitive and may be set in bold, e.g. `**Start**: ...`.
+fn parse_maintenance_window(body: &str) -> Option<MaintenanceWindow> {
+    let mut start = None;
+    let mut end = None;
+    for line in body.lines() {
+        let line = line.trim().trim_start_matches(['-', '*', ' ']);
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim_start_matches('*');
+        match key.trim_end_matches('*').trim().to_lowercase().as_str() {
+            "start" => start = parse_maintenance_time(value),
+            "end" => end = parse_maintenance_time(value),
+            _ => {}
+        }
+    }
+    match (start, end) {
+        (Some(start), Some(end)) if start < end => Some(MaintenanceWindow { start, end }),
+        _ => None,
+    }
+}
+
+impl Issue {
+    /// Whether the issue is an incident according to the `[incidents]`
+    /// configuration. The search query is up to the backend.
+    pub fn matches(&self, filter: &IncidentFilter) -> bool {
+        if let Some(label) = &filter.label {
+            if !self.labels.contains(label) {
+                return false;
+            }
+        }
+        if self
+            .labels
+            .iter()
+            .any(|label| filter.exclude_labels.contains(label))
+        {
+            return false;
+        }
+        if !filter.authors.is_empty() {
+            let allowed = self.author.as_ref().is_some_and(|author| {
+                filter
+                    .authors
+                    .iter()
+                    .any(|allowed| allowed.eq_ignore_ascii_case(author))
+            });
+            if !allowed {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn get_maintenance(&self, scheme: &LabelScheme) -> Option<MaintenanceWindow> {
+        if !self
+            .labels
+            .iter()
+            .any(|label| label == scheme.maintenance_label())
+        {
+            return None;
+        }
+        let window = self
+            .maintenance
+            .clone()
+            .or_else(|| parse_maintenance_window(&self.body));
+        if window.is_none() {
+            eprintln!(
+                "Incident {} is labeled {} but has no valid Start: and End: times, treating it as a regular incident.",
+                self.id,
+                scheme.maintenance_label()
+            );
+        }
+        window
+    }
+
+    /// The most severe status label of the issue. Maintenance uses the
+    /// `under-maintenance` level instead, as long as it is defined.
+    fn get_status(
+        &self,
+        scheme: &LabelScheme,
+        maintenance: &Option<MaintenanceWindow>,
+    ) -> Option<ComponentStatus> {
+        if let (Some(_), Some(status)) = (maintenance, scheme.maintenance_status()) {
+            return Some(status.clone());
+        }
+        self.labels
+            .iter()
+            .filter_map(|label| scheme.find_status(label))
+            .max()
+            .cloned()
+    }
+
+    /// The slugs of the affected components. A group label affects all
+    /// components of the group.
+    fn get_component_names(&self, scheme: &LabelScheme) -> Vec<String> {
+        let mut names: Vec<String> = Vec::new();
+        for slug in self
+            .labels
+            .iter()
+            .flat_map(|label| scheme.label_components(label))
+        {
+            if !names.iter().any(|name| name == slug) {
+                names.push(String::from(slug));
+            }
+        }
+        names
+    }
+
+    /// The updates, newest first. Each update is in the phase of its leading
+    /// keyword, or in the phase of the previous update if it has none. A
+    /// phase label sets the phase of the latest update instead, which is
+    /// resolved once the issue is closed. The label history isn't fetched, so
+    /// earlier updates only get a phase from their keyword. Comments the
+    /// filter keeps internal are skipped.
+    fn get_updates(&self, scheme: &LabelScheme, filter: &UpdateFilter) -> Vec<IncidentUpdate> {
+        let mut texts = Vec::with_capacity(self.comments.len() + 1);
+        texts.push((self.created_at, self.body.as_str()));
+        for comment in self.comments.iter() {
+            if let Some(body) = comment.published_body(filter) {
+                texts.push((comment.created_at, body));
+            }
+        }
+        texts.sort_by_key(|(created_at, _)| *created_at);
+
+        let latest_phase = if self.closed_at.is_some() {
+            Some(IncidentPhase::Resolved)
+        } else {
+            self.labels
+                .iter()
+                .find_map(|label| scheme.find_phase(label))
+        };
+        let mut phase = IncidentPhase::Investigating;
+        let mut updates = Vec::with_capacity(texts.len());
+        for (i, (created_at, text)) in texts.iter().enumerate() {
+            let (keyword, text) = IncidentPhase::split_keyword(text);
+            let is_latest = i + 1 == texts.len();
+            phase = keyword
+                .or(latest_phase.filter(|_| is_latest))
+                .unwrap_or(phase);
+            updates.push(IncidentUpdate::new(*created_at, phase, String::from(text)));
+        }
+        updates.reverse();
+        updates
+    }
+
+    fn get_details(&self, scheme: &LabelScheme, filter: &UpdateFilter) -> IncidentDetails {
+        let maintenance = self.get_maintenance(scheme);
+        IncidentDetails {
+            component_names: self.get_component_names(scheme),
+            id: self.id.clone(),
+            severity: self.get_status(scheme, &maintenance),
+            maintenance,
+            opened: self.created_at,
+            title: self.title.clone(),
+            updates: self.get_updates(scheme, filter),
+        }
+    }
+}
+
+pub fn get_open_incidents(
+    issues: &[Issue],
+    scheme: &LabelScheme,
+    filter: &UpdateFilter,
+) -> Vec<Incident> {
+    let mut incidents = Vec::with_capacity(issues.len());
+    for issue in issues.iter() {
+        incidents.push(Incident::new_open(issue.get_details(scheme, filter)));
+    }
+    incidents.sort();
+    incidents.reverse();
+    incidents
+}
+
+pub fn get_closed_incidents(
+    issues: &[Issue],
+    scheme: &LabelScheme,
+    filter: &UpdateFilter,
+) -> Vec<Incident> {
+    let mut incidents = Vec::with_capacity(issues.len());
+    for issue in issues.iter() {
+        incidents.push(Incident::new_closed(
+            issue.get_details(scheme, filter),
+            issue.closed_at,
+        ));
+    }
+    incidents.sort();
+    incidents
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::testing::config;
+
+    fn issue(labels: &[&str], comments: &[&str]) -> Issue {
+        let at = |minute| Utc.with_ymd_and_hms(2022, 3, 1, 10, minute, 0).unwrap();
+        Issue {
+            author: None,
+            body: String::from("We are investigating."),
+            closed_at: None,
+            comments: comments
+                .iter()
+                .zip(1..)
+                .map(|(body, minute)| Comment {
+                    author: None,
+                    body: String::from(*body),
+                    created_at: at(minute),
+                    reactions: Vec::new(),
+                })
+                .collect(),
+            created_at: at(0),
+            id: String::from("1"),
+            labels: labels.iter().map(|label| String::from(*label)).collect(),
+            maintenance: None,
+            title: String::from("Outage"),
+        }
+    }
+
+    /// The phases of the updates, oldest first.
+    fn phases(issue: &Issue) -> Vec<IncidentPhase> {
+        let scheme = LabelScheme::new(&config(""));
+        let mut updates = issue.get_updates(&scheme, &UpdateFilter::default());
+        updates.reverse();
+        updates.into_iter().map(|update| update.phase).collect()
+    }
+
+    #[test]
+    fn carries_phase_over_to_later_updates() {
+        let issue = issue(
+            &[],
+            &[
+                "**Identified**: The disk is full.",
+                "Still cleaning up.",
+                "**Monitoring:** Space is freed.",
+                "No more errors.",
+            ],
+        );
+        assert_eq!(
+            phases(&issue),
+            [
+                IncidentPhase::Investigating,
+                IncidentPhase::Identified,
+                IncidentPhase::Identified,
+                IncidentPhase::Monitoring,
+                IncidentPhase::Monitoring,
+            ]
+        );
+    }
+
+    #[test]
+    fn phase_label_sets_latest_update() {
+        let issue = issue(
+            &["phase::monitoring"],
+            &["**Identified**: The disk is full.", "Space is freed."],
+        );
+        assert_eq!(
+            phases(&issue),
+            [
+                IncidentPhase::Investigating,
+                IncidentPhase::Identified,
+                IncidentPhase::Monitoring,
+            ]
+        );
+    }
+
+    #[test]
+    fn keyword_wins_over_phase_label() {
+        let issue = issue(&["phase::monitoring"], &["**Resolved**: All good."]);
+        assert_eq!(
+            phases(&issue),
+            [IncidentPhase::Investigating, IncidentPhase::Resolved]
+        );
+    }
+}
@@ -0,0 +1,248 @@
+use std::fs::{read_to_string, File};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::types::Error;
+use crate::utils::progress;
+
+/// The URL the live reload script polls for the current build number.
+const LIVE_RELOAD_URL: &str = "/__livereload";
+
+/// Injected into every HTML page, reloads it once the build number changes.
+const LIVE_RELOAD_SCRIPT: &str = r#"<script>
+(function () {
+    var build = null;
+    setInterval(function () {
+        fetch("/__livereload", { cache: "no-store" })
+            .then(function (response) { return response.text(); })
+            .then(function (current) {
+                if (build !== null && current !== build) {
+                    location.reload();
+                }
+                build = current;
+            })
+            .catch(function () {});
+    }, 1000);
+})();
+</script>
+"#;
+
+/// Counts the builds, so open pages know when to reload.
+#[derive(Clone, Default)]
+pub struct LiveReload(Arc<AtomicU64>);
+
+impl LiveReload {
+    /// Tells the open pages that a new build is ready.
+    pub fn notify(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn build(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js") => "text/javascript; charset=utf-8",
+        Some("json") => "application/json",
+        Some("svg") => "image/svg+xml",
+        Some("xml") => "application/xml",
+        Some("atom") => "application/atom+xml",
+        Some("rss") => "application/rss+xml",
+        Some("csv") => "text/csv; charset=utf-8",
+        Some("png") => "image/png",
+        Some("ico") => "image/x-icon",
+        _ => "application/octet-stream",
+    }
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or_default();
+            if let Ok(byte) = u8::from_str_radix(hex, 16) {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Maps the URL of a request to a file below `root`, refusing anything that
+/// would escape it.
+fn resolve(root: &Path, url: &str) -> Option<PathBuf> {
+    let path = percent_decode(url.split(['?', '#']).next().unwrap_or_default());
+    let relative = Path::new(path.trim_start_matches('/'));
+    if relative
+        .components()
+        .any(|component| !matches!(component, Component::Normal(_)))
+    {
+        return None;
+    }
+    let mut path = root.join(relative);
+    if path.is_dir() {
+        path = path.join("index.html");
+    }
+    if path.is_file() {
+        Some(path)
+    } else {
+        None
+    }
+}
+
+fn not_found(request: Request) {
+    let _ = request.respond(Response::from_string("Not Found").with_status_code(404));
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name, value).expect("header names and values are valid")
+}
+
+fn respond_with_file(request: Request, path: &Path) {
+    match File::open(path) {
+        Ok(file) => {
+            let _ = request.respond(
+                Response::from_file(file).with_header(header("Content-Type", content_type(path))),
+            );
+        }
+        Err(_) => not_found(request),
+    }
+}
+
+/// Responds with an HTML page with the live reload script added to its end.
+fn respond_with_page(request: Request, path: &Path) {
+    match read_to_string(path) {
+        Ok(mut page) => {
+            let position = page.rfind("</body>").unwrap_or(page.len());
+            page.insert_str(position, LIVE_RELOAD_SCRIPT);
+            let _ = request.respond(
+                Response::from_string(page)
+                    .with_header(header("Content-Type", content_type(path)))
+                    .with_header(header("Cache-Control", "no-store")),
+            );
+        }
+        Err(_) => not_found(request),
+    }
+}
+
+/// Serves the files in `root` on a background thread until the process is
+/// stopped. Every HTML page reloads itself when `live_reload` is notified.
+pub fn spawn(root: PathBuf, bind: &str, port: u16, live_reload: LiveReload) -> Result<(), Error> {
+    let address = format!("{}:{}", bind, port);
+    let server = Server::http(&address)
+        .map_err(|e| Error::Io(format!("Cannot listen on {}: {}", address, e)))?;
+    progress!("Serving {} on http://{}/", root.display(), address);
+    thread::spawn(move || {
+        for request in server.incoming_requests() {
+            handle(&root, request, &live_reload);
+        }
+    });
+    Ok(())
+}
+
+fn handle(root: &Path, request: Request, live_reload: &LiveReload) {
+    if request.method() != &Method::Get && request.method() != &Method::Head {
+        let _ = request.respond(Response::from_string("Method Not Allowed").with_status_code(405));
+        return;
+    }
+    if request.url() == LIVE_RELOAD_URL {
+        let _ = request.respond(
+            Response::from_string(live_reload.build().to_string())
+                .with_header(header("Cache-Control", "no-store")),
+        );
+        return;
+    }
+    match resolve(root, request.url()) {
+        Some(path) if content_type(&path).starts_with("text/html") => {
+            respond_with_page(request, &path)
+        }
+        Some(path) => respond_with_file(request, &path),
+        None => not_found(request),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{create_dir_all, write};
+    use tempfile::TempDir;
+
+    use super::*;
+
+    /// A served `root` next to a `secret.txt` that must not be reachable.
+    fn site() -> (TempDir, PathBuf) {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().join("root");
+        create_dir_all(root.join("static")).unwrap();
+        create_dir_all(root.join("docs")).unwrap();
+        write(root.join("index.html"), "index").unwrap();
+        write(root.join("static").join("site.css"), "css").unwrap();
+        write(root.join("docs").join("index.html"), "docs").unwrap();
+        write(dir.path().join("secret.txt"), "secret").unwrap();
+        (dir, root)
+    }
+
+    #[test]
+    fn decodes_percent_escapes() {
+        assert_eq!(percent_decode("%2e%2E"), "..");
+        assert_eq!(percent_decode("a%20b%2Fc"), "a b/c");
+        assert_eq!(percent_decode("caf%C3%A9"), "café");
+    }
+
+    #[test]
+    fn keeps_invalid_percent_escapes() {
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%4"), "%4");
+        assert_eq!(percent_decode("%zz.html"), "%zz.html");
+    }
+
+    #[test]
+    fn resolves_files_and_directories() {
+        let (_dir, root) = site();
+        let resolve = |url| resolve(&root, url);
+        assert_eq!(resolve("/"), Some(root.join("index.html")));
+        assert_eq!(
+            resolve("/static/site.css?v=1#top"),
+            Some(root.join("static").join("site.css"))
+        );
+        assert_eq!(
+            resolve("/%73tatic/site.css"),
+            Some(root.join("static").join("site.css"))
+        );
+        assert_eq!(resolve("/docs"), Some(root.join("docs").join("index.html")));
+        assert_eq!(
+            resolve("/docs/"),
+            Some(root.join("docs").join("index.html"))
+        );
+        assert_eq!(resolve("/missing.html"), None);
+    }
+
+    #[test]
+    fn refuses_paths_outside_root() {
+        let (dir, root) = site();
+        let absolute = format!("/{}", dir.path().join("secret.txt").display());
+        for url in [
+            "/../secret.txt",
+            "/static/../../secret.txt",
+            "/%2e%2e/secret.txt",
+            "/%2E%2E%2Fsecret.txt",
+            "/static/%2e%2e/%2e%2e/secret.txt",
+            "/./index.html",
+            absolute.as_str(),
+        ] {
+            assert_eq!(resolve(&root, url), None, "{}", url);
+        }
+    }
+}
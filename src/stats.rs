@@ -0,0 +1,318 @@
+//! Incident statistics over all fetched incidents, for the `stats.html` page
+//! and as `stats.json`, `stats.csv` (one row per month) and `incidents.csv`
+//! (one row per incident).
+//!
+//! Scheduled maintenance isn't an incident here. Open incidents count until
+//! now. The issues don't record when a problem was detected, the time to
+//! identify runs from opening the incident to its first identified or
+//! monitoring update instead.
+
+use chrono::prelude::*;
+use chrono::Duration;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs::write;
+use std::path::Path;
+
+use crate::types::{ComponentStatus, Components, Error, Incident, IncidentPhase};
+use crate::utils::progress;
+use crate::writer::write_json;
+
+/// The built-in statistics template.
+pub const TEMPLATES: &[(&str, &str)] = &[("stats.html", include_str!("../templates/stats.html"))];
+
+/// A duration, in seconds and as text like `1d 2h 5m`.
+#[derive(Debug, Serialize)]
+pub struct Span {
+    pub seconds: i64,
+    pub text: String,
+}
+
+impl From<Duration> for Span {
+    fn from(duration: Duration) -> Self {
+        let minutes = duration.num_minutes();
+        let (days, hours, minutes) = (minutes / 1440, minutes / 60 % 24, minutes % 60);
+        let mut parts = Vec::new();
+        if days > 0 {
+            parts.push(format!("{}d", days));
+        }
+        if hours > 0 {
+            parts.push(format!("{}h", hours));
+        }
+        if minutes > 0 || parts.is_empty() {
+            parts.push(format!("{}m", minutes));
+        }
+        Span {
+            seconds: duration.num_seconds(),
+            text: parts.join(" "),
+        }
+    }
+}
+
+/// The incidents of one component or severity.
+#[derive(Debug, Serialize)]
+pub struct GroupStats {
+    pub key: String,
+    pub name: String,
+    pub incidents: usize,
+    pub downtime: Span,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MonthStats {
+    /// Like `2022-03`, by the month the incidents were opened in.
+    pub month: String,
+    pub incidents: usize,
+    pub resolved: usize,
+    pub mean_time_to_resolve: Option<Span>,
+    pub downtime: Span,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LongestOutage {
+    pub id: String,
+    pub title: String,
+    pub duration: Span,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Statistics {
+    pub incidents: usize,
+    pub open: usize,
+    pub resolved: usize,
+    pub mean_time_to_identify: Option<Span>,
+    pub mean_time_to_resolve: Option<Span>,
+    pub longest_outage: Option<LongestOutage>,
+    /// In the order of the components, without hidden ones.
+    pub components: Vec<GroupStats>,
+    /// Most severe first.
+    pub severities: Vec<GroupStats>,
+    /// Newest first.
+    pub months: Vec<MonthStats>,
+}
+
+/// How long the incident lasted, until now if it is still open.
+fn duration(incident: &Incident, now: DateTime<Utc>) -> Duration {
+    incident.closed.unwrap_or(now) - incident.opened
+}
+
+fn time_to_resolve(incident: &Incident) -> Option<Duration> {
+    incident.closed.map(|closed| closed - incident.opened)
+}
+
+/// Until the first identified or monitoring update, or until the incident was
+/// closed if it skipped these phases.
+fn time_to_identify(incident: &Incident) -> Option<Duration> {
+    incident
+        .updates
+        .iter()
+        .rev()
+        .find(|update| {
+            matches!(
+                update.phase,
+                IncidentPhase::Identified | IncidentPhase::Monitoring
+            )
+        })
+        .map(|update| update.datetime)
+        .or(incident.closed)
+        .map(|identified| identified - incident.opened)
+}
+
+fn mean(durations: impl Iterator<Item = Duration>) -> Option<Span> {
+    let (count, total) = durations.fold((0, Duration::zero()), |(count, total), duration| {
+        (count + 1, total + duration)
+    });
+    (count > 0).then(|| Span::from(total / count))
+}
+
+/// The summed up duration of `incidents`.
+fn downtime(incidents: &[&Incident], now: DateTime<Utc>) -> Span {
+    Span::from(
+        incidents
+            .iter()
+            .map(|incident| duration(incident, now))
+            .fold(Duration::zero(), |total, duration| total + duration),
+    )
+}
+
+fn group_stats(key: &str, name: &str, incidents: &[&Incident], now: DateTime<Utc>) -> GroupStats {
+    GroupStats {
+        key: String::from(key),
+        name: String::from(name),
+        incidents: incidents.len(),
+        downtime: downtime(incidents, now),
+    }
+}
+
+pub fn get_statistics(
+    components: &Components,
+    statuses: &[ComponentStatus],
+    incidents: &[&Incident],
+    now: DateTime<Utc>,
+) -> Statistics {
+    let incidents: Vec<&Incident> = incidents
+        .iter()
+        .copied()
+        .filter(|incident| incident.maintenance.is_none())
+        .collect();
+    let resolved = incidents
+        .iter()
+        .filter(|incident| incident.closed.is_some())
+        .count();
+
+    let component_stats = components
+        .iter()
+        .filter(|(_, component)| !component.hidden)
+        .map(|(slug, component)| {
+            let affected: Vec<&Incident> = incidents
+                .iter()
+                .copied()
+                .filter(|incident| incident.affects(slug))
+                .collect();
+            group_stats(slug, &component.name, &affected, now)
+        })
+        .collect();
+
+    let mut statuses: Vec<&ComponentStatus> = statuses.iter().collect();
+    statuses.sort();
+    let severity_stats = statuses
+        .into_iter()
+        .rev()
+        .map(|status| {
+            let matching: Vec<&Incident> = incidents
+                .iter()
+                .copied()
+                .filter(|incident| incident.severity.as_ref() == Some(status))
+                .collect();
+            group_stats(&status.key, &status.name, &matching, now)
+        })
+        .filter(|stats| stats.incidents > 0)
+        .collect();
+
+    let mut by_month: BTreeMap<String, Vec<&Incident>> = BTreeMap::new();
+    for incident in incidents.iter() {
+        by_month
+            .entry(incident.opened.format("%Y-%m").to_string())
+            .or_default()
+            .push(incident);
+    }
+    let months = by_month
+        .into_iter()
+        .rev()
+        .map(|(month, incidents)| MonthStats {
+            month,
+            incidents: incidents.len(),
+            resolved: incidents
+                .iter()
+                .filter(|incident| incident.closed.is_some())
+                .count(),
+            mean_time_to_resolve: mean(incidents.iter().filter_map(|i| time_to_resolve(i))),
+            downtime: downtime(&incidents, now),
+        })
+        .collect();
+
+    Statistics {
+        incidents: incidents.len(),
+        open: incidents.len() - resolved,
+        resolved,
+        mean_time_to_identify: mean(incidents.iter().filter_map(|i| time_to_identify(i))),
+        mean_time_to_resolve: mean(incidents.iter().filter_map(|i| time_to_resolve(i))),
+        longest_outage: incidents
+            .iter()
+            .max_by_key(|incident| duration(incident, now))
+            .map(|incident| LongestOutage {
+                id: incident.id.clone(),
+                title: incident.title.clone(),
+                duration: Span::from(duration(incident, now)),
+            }),
+        components: component_stats,
+        severities: severity_stats,
+        months,
+    }
+}
+
+/// Quotes a CSV field if needed.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        String::from(value)
+    }
+}
+
+fn write_csv(rows: &[Vec<String>], path: &Path) -> Result<(), Error> {
+    progress!("Writing file {} ...", path.display());
+    let mut content = String::new();
+    for row in rows.iter() {
+        let fields: Vec<String> = row.iter().map(|field| csv_field(field)).collect();
+        content.push_str(&fields.join(","));
+        content.push_str("\r\n");
+    }
+    write(path, content).map_err(|e| Error::io(path, e))
+}
+
+fn optional_seconds(duration: Option<Duration>) -> String {
+    duration.map_or_else(String::new, |duration| duration.num_seconds().to_string())
+}
+
+/// Writes `stats.json`, `stats.csv` and `incidents.csv` to `output_dir`.
+pub fn write_exports(
+    stats: &Statistics,
+    incidents: &[&Incident],
+    output_dir: &Path,
+) -> Result<(), Error> {
+    write_json(stats, output_dir.join("stats.json"))?;
+
+    let mut months = vec![vec![
+        String::from("month"),
+        String::from("incidents"),
+        String::from("resolved"),
+        String::from("mean_time_to_resolve_seconds"),
+        String::from("downtime_seconds"),
+    ]];
+    for month in stats.months.iter() {
+        months.push(vec![
+            month.month.clone(),
+            month.incidents.to_string(),
+            month.resolved.to_string(),
+            month
+                .mean_time_to_resolve
+                .as_ref()
+                .map_or_else(String::new, |span| span.seconds.to_string()),
+            month.downtime.seconds.to_string(),
+        ]);
+    }
+    write_csv(&months, &output_dir.join("stats.csv"))?;
+
+    let mut rows = vec![vec![
+        String::from("id"),
+        String::from("title"),
+        String::from("severity"),
+        String::from("components"),
+        String::from("opened"),
+        String::from("closed"),
+        String::from("time_to_identify_seconds"),
+        String::from("time_to_resolve_seconds"),
+    ]];
+    for incident in incidents
+        .iter()
+        .filter(|incident| incident.maintenance.is_none())
+    {
+        rows.push(vec![
+            incident.id.clone(),
+            incident.title.clone(),
+            incident
+                .severity
+                .as_ref()
+                .map_or_else(String::new, |severity| severity.key.clone()),
+            incident.component_names.join(" "),
+            incident.opened.to_rfc3339_opts(SecondsFormat::Secs, true),
+            incident.closed.map_or_else(String::new, |closed| {
+                closed.to_rfc3339_opts(SecondsFormat::Secs, true)
+            }),
+            optional_seconds(time_to_identify(incident)),
+            optional_seconds(time_to_resolve(incident)),
+        ]);
+    }
+    write_csv(&rows, &output_dir.join("incidents.csv"))
+}
@@ -0,0 +1,357 @@
+//! Files in the schema of the Atlassian Statuspage.io API, written to
+//! `api/v2` when the configuration has a `[statuspage]` section. Tools that
+//! read `summary.json`, `status.json`, `components.json` and `incidents.json`
+//! of a Statuspage.io page can read these, too.
+//!
+//! Scheduled maintenance is listed in the `scheduled_maintenances` of the
+//! summary instead of the incidents. Update bodies are HTML. Component groups
+//! are components with `group: true` and the IDs of their `components`, which
+//! point back with their `group_id`.
+
+use chrono::prelude::*;
+use serde::Serialize;
+use std::fs::create_dir_all;
+
+use crate::config::{Config, Statuspage, STATUSPAGE_STATUSES};
+use crate::types::{
+    overall_status, Component, ComponentGroup, ComponentStatus, Components, Error, Incident,
+    IncidentPhase, IncidentStatus, IncidentUpdate,
+};
+use crate::writer::write_json;
+
+#[derive(Debug, Serialize)]
+struct SpPage<'a> {
+    id: &'a str,
+    name: &'a str,
+    url: &'a str,
+    time_zone: &'static str,
+    updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+struct SpStatus {
+    indicator: &'static str,
+    description: &'static str,
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct SpComponent<'a> {
+    id: &'a str,
+    name: &'a str,
+    status: &'a str,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    position: usize,
+    description: Option<&'a str>,
+    showcase: bool,
+    start_date: Option<&'a str>,
+    group_id: Option<&'a str>,
+    page_id: &'a str,
+    group: bool,
+    only_show_if_degraded: bool,
+    /// The IDs of the components of a group.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    components: Option<Vec<&'a str>>,
+}
+
+#[derive(Debug, Serialize)]
+struct SpIncidentUpdate<'a> {
+    id: String,
+    status: &'static str,
+    body: &'a str,
+    incident_id: &'a str,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    display_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+struct SpIncident<'a> {
+    id: &'a str,
+    name: &'a str,
+    status: &'static str,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    monitoring_at: Option<DateTime<Utc>>,
+    resolved_at: Option<DateTime<Utc>>,
+    impact: &'static str,
+    shortlink: String,
+    started_at: DateTime<Utc>,
+    page_id: &'a str,
+    scheduled_for: Option<DateTime<Utc>>,
+    scheduled_until: Option<DateTime<Utc>>,
+    incident_updates: Vec<SpIncidentUpdate<'a>>,
+    components: Vec<SpComponent<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct SpSummary<'a> {
+    page: &'a SpPage<'a>,
+    status: SpStatus,
+    components: &'a [SpComponent<'a>],
+    incidents: Vec<&'a SpIncident<'a>>,
+    scheduled_maintenances: Vec<&'a SpIncident<'a>>,
+}
+
+/// The status indicator matching a component status.
+fn indicator(status: &str) -> &'static str {
+    match status {
+        "under_maintenance" => "maintenance",
+        "degraded_performance" => "minor",
+        "partial_outage" => "major",
+        "major_outage" => "critical",
+        _ => "none",
+    }
+}
+
+fn describe(indicator: &str) -> &'static str {
+    match indicator {
+        "maintenance" => "Service Under Maintenance",
+        "minor" => "Minor Service Outage",
+        "major" => "Partial System Outage",
+        "critical" => "Major Service Outage",
+        _ => "All Systems Operational",
+    }
+}
+
+/// The maintenance status: scheduled, in progress or completed.
+fn maintenance_status(incident: &Incident) -> &'static str {
+    match incident.status {
+        IncidentStatus::Scheduled => "scheduled",
+        IncidentStatus::Open => "in_progress",
+        IncidentStatus::Closed => "completed",
+    }
+}
+
+struct Writer<'a> {
+    settings: &'a Statuspage,
+    base_url: &'a str,
+    default_status: &'a ComponentStatus,
+    now: DateTime<Utc>,
+}
+
+impl<'a> Writer<'a> {
+    /// Maps a status level by the configured mapping or its key. Unknown
+    /// levels are a major outage, unless they are the lowest level.
+    fn component_status(&self, status: &'a ComponentStatus) -> &'a str {
+        if let Some(mapped) = self.settings.statuses.get(&status.key) {
+            return mapped;
+        }
+        let key = status.key.replace('-', "_");
+        match STATUSPAGE_STATUSES.iter().find(|known| **known == key) {
+            Some(known) => known,
+            None if status == self.default_status => "operational",
+            None => "major_outage",
+        }
+    }
+
+    fn component(
+        &self,
+        slug: &'a str,
+        component: &'a Component,
+        position: usize,
+    ) -> SpComponent<'a> {
+        SpComponent {
+            id: slug,
+            name: &component.name,
+            status: self.component_status(&component.status),
+            created_at: self.now,
+            updated_at: self.now,
+            position,
+            description: component.description.as_deref(),
+            showcase: true,
+            start_date: None,
+            group_id: component.group.as_deref(),
+            page_id: &self.settings.page_id,
+            group: false,
+            only_show_if_degraded: false,
+            components: None,
+        }
+    }
+
+    /// A group entry listing the IDs of its public `components`.
+    fn group(
+        &self,
+        group: &'a ComponentGroup,
+        components: Vec<&'a str>,
+        position: usize,
+    ) -> SpComponent<'a> {
+        SpComponent {
+            id: &group.slug,
+            name: &group.name,
+            status: self.component_status(&group.status),
+            created_at: self.now,
+            updated_at: self.now,
+            position,
+            description: None,
+            showcase: true,
+            start_date: None,
+            group_id: None,
+            page_id: &self.settings.page_id,
+            group: true,
+            only_show_if_degraded: false,
+            components: Some(components),
+        }
+    }
+
+    fn update(
+        &self,
+        incident: &'a Incident,
+        update: &'a IncidentUpdate,
+        status: &'static str,
+    ) -> SpIncidentUpdate<'a> {
+        SpIncidentUpdate {
+            id: format!("{}-{}", incident.id, update.datetime.timestamp()),
+            status,
+            body: &update.text,
+            incident_id: &incident.id,
+            created_at: update.datetime,
+            updated_at: update.datetime,
+            display_at: update.datetime,
+        }
+    }
+
+    fn incident(&self, incident: &'a Incident, components: &[SpComponent<'a>]) -> SpIncident<'a> {
+        let (status, impact) = match &incident.maintenance {
+            Some(_) => (maintenance_status(incident), "maintenance"),
+            None => (
+                incident.phase.key(),
+                incident.severity.as_ref().map_or("none", |severity| {
+                    indicator(self.component_status(severity))
+                }),
+            ),
+        };
+        let incident_updates = incident
+            .updates
+            .iter()
+            .enumerate()
+            .map(|(i, update)| {
+                let status = match &incident.maintenance {
+                    Some(_) if i == 0 => status,
+                    Some(_) => "scheduled",
+                    None => update.phase.key(),
+                };
+                self.update(incident, update, status)
+            })
+            .collect();
+        SpIncident {
+            id: &incident.id,
+            name: &incident.title,
+            status,
+            created_at: incident.opened,
+            updated_at: incident.updated(),
+            monitoring_at: incident
+                .updates
+                .iter()
+                .rev()
+                .find(|update| update.phase == IncidentPhase::Monitoring)
+                .map(|update| update.datetime),
+            resolved_at: incident.closed,
+            impact,
+            shortlink: format!("{}{}.html", self.base_url, incident.id),
+            started_at: incident.opened,
+            page_id: &self.settings.page_id,
+            scheduled_for: incident.maintenance.as_ref().map(|window| window.start),
+            scheduled_until: incident.maintenance.as_ref().map(|window| window.end),
+            incident_updates,
+            components: components
+                .iter()
+                .filter(|component| incident.affects(component.id))
+                .cloned()
+                .collect(),
+        }
+    }
+}
+
+/// Writes the Statuspage.io compatible files to `api/v2` in the output
+/// directory. `default_status` is the lowest status level. Groups follow the
+/// components, unless all of their components are hidden.
+pub fn write_statuspage(
+    config: &Config,
+    settings: &Statuspage,
+    components: &Components,
+    component_groups: &[ComponentGroup],
+    incidents: &[&Incident],
+    default_status: &ComponentStatus,
+    now: DateTime<Utc>,
+) -> Result<(), Error> {
+    let now = now.trunc_subsecs(0);
+    let api_dir = config.output_dir.join("api").join("v2");
+    create_dir_all(&api_dir).map_err(|e| Error::io(&api_dir, e))?;
+    let writer = Writer {
+        settings,
+        base_url: config.base_url.as_deref().unwrap_or(""),
+        default_status,
+        now,
+    };
+    let page = SpPage {
+        id: &settings.page_id,
+        name: &settings.name,
+        url: writer.base_url,
+        time_zone: "Etc/UTC",
+        updated_at: now,
+    };
+
+    let mut sp_components: Vec<SpComponent> = components
+        .iter()
+        .filter(|(_, component)| !component.hidden)
+        .enumerate()
+        .map(|(position, (slug, component))| writer.component(slug, component, position + 1))
+        .collect();
+    for group in component_groups.iter() {
+        let children: Vec<&str> = sp_components
+            .iter()
+            .filter(|component| component.group_id == Some(group.slug.as_str()))
+            .map(|component| component.id)
+            .collect();
+        if !children.is_empty() {
+            let position = sp_components.len() + 1;
+            sp_components.push(writer.group(group, children, position));
+        }
+    }
+    let sp_incidents: Vec<SpIncident> = incidents
+        .iter()
+        .map(|incident| writer.incident(incident, &sp_components))
+        .collect();
+
+    let worst = overall_status(components, default_status);
+    let status = || {
+        let indicator = indicator(writer.component_status(worst));
+        SpStatus {
+            indicator,
+            description: describe(indicator),
+        }
+    };
+
+    write_json(
+        &serde_json::json!({ "page": &page, "status": status() }),
+        api_dir.join("status.json"),
+    )?;
+    write_json(
+        &serde_json::json!({ "page": &page, "components": &sp_components }),
+        api_dir.join("components.json"),
+    )?;
+    let (maintenances, regular): (Vec<&SpIncident>, Vec<&SpIncident>) = sp_incidents
+        .iter()
+        .partition(|incident| incident.impact == "maintenance");
+    write_json(
+        &serde_json::json!({ "page": &page, "incidents": &regular }),
+        api_dir.join("incidents.json"),
+    )?;
+    let summary = SpSummary {
+        page: &page,
+        status: status(),
+        components: &sp_components,
+        incidents: regular
+            .iter()
+            .copied()
+            .filter(|incident| incident.resolved_at.is_none())
+            .collect(),
+        scheduled_maintenances: maintenances
+            .iter()
+            .copied()
+            .filter(|incident| incident.status != "completed")
+            .collect(),
+    };
+    write_json(&summary, api_dir.join("summary.json"))
+}
@@ -0,0 +1,102 @@
+//! Helpers for the unit tests: a stub HTTP server standing in for the issue
+//! tracker APIs, and configurations parsed from TOML snippets.
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tiny_http::{Header, Response, Server};
+
+use crate::config::Config;
+
+/// A request the stub server received.
+#[derive(Clone, Debug)]
+pub struct MockRequest {
+    /// The path and query, exactly as sent.
+    pub url: String,
+}
+
+/// What the stub server answers with.
+pub struct MockResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl MockResponse {
+    pub fn json(body: serde_json::Value) -> Self {
+        MockResponse {
+            status: 200,
+            headers: vec![(
+                String::from("Content-Type"),
+                String::from("application/json"),
+            )],
+            body: body.to_string(),
+        }
+    }
+
+    pub fn status(mut self, status: u16) -> Self {
+        self.status = status;
+        self
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((String::from(name), String::from(value)));
+        self
+    }
+}
+
+/// An HTTP server on a random local port, answering every request with the
+/// handler until the test process ends.
+pub struct MockServer {
+    pub url: String,
+    requests: Arc<Mutex<Vec<MockRequest>>>,
+}
+
+impl MockServer {
+    pub fn start<F>(handler: F) -> Self
+    where
+        F: Fn(&MockRequest) -> MockResponse + Send + 'static,
+    {
+        let server = Server::http("127.0.0.1:0").expect("cannot start the stub server");
+        let port = server
+            .server_addr()
+            .to_ip()
+            .expect("the stub server listens on TCP")
+            .port();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&requests);
+        thread::spawn(move || {
+            for request in server.incoming_requests() {
+                let mock_request = MockRequest {
+                    url: request.url().to_string(),
+                };
+                let response = handler(&mock_request);
+                recorded.lock().unwrap().push(mock_request);
+                let mut reply =
+                    Response::from_string(response.body).with_status_code(response.status);
+                for (name, value) in response.headers.iter() {
+                    reply.add_header(Header::from_bytes(name.as_str(), value.as_str()).unwrap());
+                }
+                let _ = request.respond(reply);
+            }
+        });
+        MockServer {
+            url: format!("http://127.0.0.1:{}", port),
+            requests,
+        }
+    }
+
+    /// The requests received so far, oldest first.
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+/// A configuration of the files backend with `extra` appended, without the
+/// checks of [`Config::load`].
+pub fn config(extra: &str) -> Config {
+    toml::from_str(&format!(
+        "backend = \"files\"\nstatic_directory = \".\"\n{}",
+        extra
+    ))
+    .expect("invalid test configuration")
+}
@@ -1,56 +1,201 @@
 use chrono::prelude::*;
 use chrono::serde::ts_seconds::serialize as to_seconds;
 use chrono::serde::ts_seconds_option::serialize as to_seconds_optional;
-use serde::Serialize;
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::path::Path;
 
+use crate::config::ComponentConfig;
 use crate::utils::render_markdown;
 
-#[derive(Clone, Copy, Debug, Serialize, PartialEq, PartialOrd)]
-pub enum ComponentStatus {
-    Unknown,
-    Operational,
-    PartialOutage,
-    MajorOutage,
+/// A status level as defined by the `[[statuses]]` in the configuration.
+/// Levels are ordered by their rank, a higher rank is more severe.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ComponentStatus {
+    /// Identifies the level in labels, templates and front matter.
+    pub key: String,
+    /// The text shown on the status page.
+    pub name: String,
+    /// A CSS hex color like `#22c55e`.
+    pub color: String,
+    pub rank: u32,
+    /// The label in the backend, defaults to the status prefix followed by
+    /// the key.
+    #[serde(default, skip_serializing)]
+    pub label: Option<String>,
 }
 
+impl Ord for ComponentStatus {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.rank
+            .cmp(&other.rank)
+            .then_with(|| self.key.cmp(&other.key))
+    }
+}
+impl PartialOrd for ComponentStatus {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl PartialEq for ComponentStatus {
+    fn eq(&self, other: &Self) -> bool {
+        self.rank == other.rank && self.key == other.key
+    }
+}
+impl Eq for ComponentStatus {}
+
 #[derive(Debug, Serialize)]
 pub struct Component {
-    name: String,
-    status: ComponentStatus,
+    pub name: String,
+    pub description: Option<String>,
+    pub order: Option<i64>,
+    pub url: Option<String>,
+    pub hidden: bool,
+    pub status: ComponentStatus,
+    /// The slug of the group the component belongs to.
+    pub group: Option<String>,
 }
 impl Component {
-    pub fn new(name: String) -> Self {
+    pub fn new(config: &ComponentConfig, status: ComponentStatus) -> Self {
         Component {
-            name,
-            status: ComponentStatus::Operational,
+            name: config.name.clone(),
+            description: config.description.clone(),
+            order: config.order,
+            url: config.url.clone(),
+            hidden: config.hidden,
+            status,
+            group: None,
         }
     }
-    pub fn bump_status(&mut self, status: ComponentStatus) {
-        if status > self.status {
-            self.status = status
+    pub fn bump_status(&mut self, status: &ComponentStatus) {
+        if *status > self.status {
+            self.status = status.clone()
         }
     }
 }
 
+/// The components by their slug, in the order they are shown.
+pub type Components = IndexMap<String, Component>;
+
+/// Components shown together, in the status of its most severely affected
+/// component.
 #[derive(Debug, Serialize)]
+pub struct ComponentGroup {
+    pub slug: String,
+    pub name: String,
+    pub status: ComponentStatus,
+    /// The slugs of the components, in the configured order.
+    pub components: Vec<String>,
+    pub collapsed: bool,
+}
+
+/// The most severe status of all public components, `default` if there are
+/// none.
+pub fn overall_status<'a>(
+    components: &'a Components,
+    default: &'a ComponentStatus,
+) -> &'a ComponentStatus {
+    components
+        .values()
+        .filter(|component| !component.hidden)
+        .map(|component| &component.status)
+        .max()
+        .unwrap_or(default)
+}
+
+#[derive(Debug, Serialize, PartialEq)]
 pub enum IncidentStatus {
     Open,
+    /// Maintenance that hasn't started yet.
+    Scheduled,
     Closed,
 }
 
+impl IncidentStatus {
+    pub fn key(&self) -> &'static str {
+        match self {
+            IncidentStatus::Open => "open",
+            IncidentStatus::Scheduled => "scheduled",
+            IncidentStatus::Closed => "closed",
+        }
+    }
+}
+
+/// The time frame of a scheduled maintenance.
+#[derive(Clone, Debug, Serialize)]
+pub struct MaintenanceWindow {
+    #[serde(serialize_with = "to_seconds")]
+    pub start: DateTime<Utc>,
+    #[serde(serialize_with = "to_seconds")]
+    pub end: DateTime<Utc>,
+}
+
+/// The stage of the incident response an update belongs to.
+#[derive(Clone, Copy, Debug, Serialize, PartialEq)]
+pub enum IncidentPhase {
+    Investigating,
+    Identified,
+    Monitoring,
+    Resolved,
+}
+
+impl IncidentPhase {
+    pub const ALL: [IncidentPhase; 4] = [
+        IncidentPhase::Investigating,
+        IncidentPhase::Identified,
+        IncidentPhase::Monitoring,
+        IncidentPhase::Resolved,
+    ];
+
+    /// The name used in phase labels and update keywords.
+    pub fn key(&self) -> &'static str {
+        match self {
+            IncidentPhase::Investigating => "investigating",
+            IncidentPhase::Identified => "identified",
+            IncidentPhase::Monitoring => "monitoring",
+            IncidentPhase::Resolved => "resolved",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        IncidentPhase::ALL
+            .into_iter()
+            .find(|phase| phase.key().eq_ignore_ascii_case(key))
+    }
+
+    /// Splits a leading keyword like `**Identified**:`, `**Identified:**` or
+    /// `Identified:` off the text of an update.
+    pub fn split_keyword(text: &str) -> (Option<Self>, &str) {
+        let text = text.trim_start();
+        if let Some((keyword, rest)) = text.split_once(':') {
+            if let Some(phase) = IncidentPhase::from_key(keyword.trim_matches(['*', '_'])) {
+                return (
+                    Some(phase),
+                    rest.trim_start_matches(['*', '_']).trim_start(),
+                );
+            }
+        }
+        (None, text)
+    }
+}
+
 #[derive(Debug, Serialize)]
 pub struct IncidentUpdate {
     #[serde(serialize_with = "to_seconds")]
-    datetime: DateTime<Utc>,
-    text: String,
+    pub datetime: DateTime<Utc>,
+    pub phase: IncidentPhase,
+    /// The text rendered to HTML.
+    pub text: String,
 }
 
 impl IncidentUpdate {
-    pub fn new(datetime: DateTime<Utc>, text: String) -> Self {
+    pub fn new(datetime: DateTime<Utc>, phase: IncidentPhase, text: String) -> Self {
         IncidentUpdate {
             datetime,
+            phase,
             text: render_markdown(text),
         }
     }
@@ -76,81 +221,129 @@ impl Eq for IncidentUpdate {}
 #[derive(Debug, Serialize)]
 pub struct Incident {
     #[serde(serialize_with = "to_seconds_optional")]
-    closed: Option<DateTime<Utc>>,
-    component_names: Option<Vec<String>>,
+    pub closed: Option<DateTime<Utc>>,
+    pub component_names: Vec<String>,
     pub id: String,
+    pub maintenance: Option<MaintenanceWindow>,
     #[serde(serialize_with = "to_seconds")]
-    opened: DateTime<Utc>,
-    severity: ComponentStatus,
-    status: IncidentStatus,
-    title: String,
-    updates: Vec<IncidentUpdate>,
+    pub opened: DateTime<Utc>,
+    /// The phase of the latest update, closed incidents are resolved.
+    pub phase: IncidentPhase,
+    /// `None` if the incident has no status label.
+    pub severity: Option<ComponentStatus>,
+    pub status: IncidentStatus,
+    pub title: String,
+    /// The updates, newest first.
+    pub updates: Vec<IncidentUpdate>,
+}
+
+/// What open and closed incidents have in common.
+pub struct IncidentDetails {
+    pub component_names: Vec<String>,
+    pub id: String,
+    pub maintenance: Option<MaintenanceWindow>,
+    pub opened: DateTime<Utc>,
+    pub severity: Option<ComponentStatus>,
+    pub title: String,
+    pub updates: Vec<IncidentUpdate>,
 }
 
 impl Incident {
-    pub fn new_open(
-        component_names: Vec<String>,
-        id: String,
-        opened: DateTime<Utc>,
-        severity: ComponentStatus,
-        title: String,
-        updates: Vec<IncidentUpdate>,
-    ) -> Self {
-        Incident {
-            closed: None,
-            component_names: Some(component_names),
-            id,
-            opened,
-            severity,
-            status: IncidentStatus::Open,
-            title,
-            updates,
-        }
+    pub fn new_open(details: IncidentDetails) -> Self {
+        let phase = details
+            .updates
+            .first()
+            .map_or(IncidentPhase::Investigating, |update| update.phase);
+        Incident::new(details, None, phase, IncidentStatus::Open)
     }
-    pub fn new_closed(
+    pub fn new_closed(details: IncidentDetails, closed: Option<DateTime<Utc>>) -> Self {
+        Incident::new(
+            details,
+            closed,
+            IncidentPhase::Resolved,
+            IncidentStatus::Closed,
+        )
+    }
+    fn new(
+        details: IncidentDetails,
         closed: Option<DateTime<Utc>>,
-        id: String,
-        opened: DateTime<Utc>,
-        severity: ComponentStatus,
-        title: String,
-        updates: Vec<IncidentUpdate>,
+        phase: IncidentPhase,
+        status: IncidentStatus,
     ) -> Self {
         Incident {
             closed,
-            component_names: None,
-            id,
-            opened,
-            severity,
-            status: IncidentStatus::Closed,
-            title,
-            updates,
-        }
-    }
-
-    pub fn update_components(&self, components: &mut HashMap<String, Component>) {
-        match &self.component_names {
-            Some(names) => {
-                for name in names.iter() {
-                    match components.get_mut(name) {
-                        Some(component) => component.bump_status(self.severity),
-                        _ => {}
-                    }
-                }
+            component_names: details.component_names,
+            id: details.id,
+            maintenance: details.maintenance,
+            opened: details.opened,
+            phase,
+            severity: details.severity,
+            status,
+            title: details.title,
+            updates: details.updates,
+        }
+    }
+
+    /// When the incident last changed, either by an update or by closing it.
+    pub fn updated(&self) -> DateTime<Utc> {
+        self.updates
+            .iter()
+            .map(|update| update.datetime)
+            .chain(self.closed)
+            .fold(self.opened, DateTime::max)
+    }
+
+    /// Drops hidden components from the affected components, once they have
+    /// counted towards the component status.
+    pub fn hide_components(&mut self, components: &Components) {
+        self.component_names
+            .retain(|name| components.get(name).is_none_or(|c| !c.hidden));
+    }
+
+    /// Whether the component with the slug `name` is affected.
+    pub fn affects(&self, name: &str) -> bool {
+        self.component_names.iter().any(|n| n == name)
+    }
+
+    /// Moves open maintenance that hasn't started yet to
+    /// [`IncidentStatus::Scheduled`] and closes it once its window has ended.
+    pub fn schedule(&mut self, now: DateTime<Utc>) {
+        if self.status != IncidentStatus::Open {
+            return;
+        }
+        if let Some(window) = &self.maintenance {
+            if now < window.start {
+                self.status = IncidentStatus::Scheduled;
+            } else if now >= window.end {
+                self.status = IncidentStatus::Closed;
+                self.closed = Some(window.end);
+                self.phase = IncidentPhase::Resolved;
+            }
+        }
+    }
+
+    /// Raises the status of the affected components to the severity of this
+    /// incident. Closed incidents don't affect the current status.
+    pub fn update_components(&self, components: &mut Components) {
+        let Some(severity) = &self.severity else {
+            return;
+        };
+        if self.status != IncidentStatus::Open {
+            return;
+        }
+        for name in self.component_names.iter() {
+            if let Some(component) = components.get_mut(name) {
+                component.bump_status(severity);
             }
-            _ => {}
         }
     }
 }
 
 impl Ord for Incident {
     fn cmp(&self, other: &Self) -> Ordering {
-        if self.severity == other.severity {
-            self.opened.cmp(&other.opened).reverse()
-        } else if self.severity < other.severity {
-            Ordering::Less
-        } else {
-            Ordering::Greater
-        }
+        self.severity
+            .cmp(&other.severity)
+            .then_with(|| self.opened.cmp(&other.opened).reverse())
     }
 }
 impl PartialOrd for Incident {
@@ -165,5 +358,120 @@ impl PartialEq for Incident {
 }
 impl Eq for Incident {}
 
+/// Everything that can go wrong while building the status page. Each class of
+/// failure exits the process with its own code, see [`Error::exit_code`].
 #[derive(Debug)]
-pub enum Error {}
+pub enum Error {
+    Config(String),
+    Network(String),
+    Api(String),
+    Parse(String),
+    Template(String),
+    Io(String),
+}
+
+impl Error {
+    pub fn io(path: &Path, err: io::Error) -> Self {
+        Error::Io(format!("{}: {}", path.display(), err))
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Config(_) => 2,
+            Error::Network(_) => 3,
+            Error::Api(_) => 4,
+            Error::Parse(_) => 5,
+            Error::Template(_) => 6,
+            Error::Io(_) => 7,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Config(msg) => write!(f, "Configuration error: {}", msg),
+            Error::Network(msg) => write!(f, "Network error: {}", msg),
+            Error::Api(msg) => write!(f, "API error: {}", msg),
+            Error::Parse(msg) => write!(f, "Parse error: {}", msg),
+            Error::Template(msg) => write!(f, "Template error: {}", msg),
+            Error::Io(msg) => write!(f, "I/O error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<reqwest::Error> for Error {
+    fn from(err: reqwest::Error) -> Self {
+        if err.is_decode() {
+            Error::Parse(err.to_string())
+        } else if err.is_status() {
+            Error::Api(err.to_string())
+        } else {
+            Error::Network(err.to_string())
+        }
+    }
+}
+
+impl From<tera::Error> for Error {
+    fn from(err: tera::Error) -> Self {
+        // Tera's top-level message rarely says what went wrong, the details
+        // are in the chain of sources.
+        let mut msg = err.to_string();
+        let mut source = std::error::Error::source(&err);
+        while let Some(err) = source {
+            msg.push_str(&format!(": {}", err));
+            source = err.source();
+        }
+        Error::Template(msg)
+    }
+}
+
+impl From<toml::de::Error> for Error {
+    fn from(err: toml::de::Error) -> Self {
+        Error::Config(err.to_string())
+    }
+}
+
+impl From<chrono::ParseError> for Error {
+    fn from(err: chrono::ParseError) -> Self {
+        Error::Parse(err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_bold_keyword() {
+        assert_eq!(
+            IncidentPhase::split_keyword("**Identified**: The disk is full."),
+            (Some(IncidentPhase::Identified), "The disk is full.")
+        );
+    }
+
+    #[test]
+    fn splits_keyword_with_bold_colon() {
+        assert_eq!(
+            IncidentPhase::split_keyword("**Identified:** The disk is full."),
+            (Some(IncidentPhase::Identified), "The disk is full.")
+        );
+        assert_eq!(
+            IncidentPhase::split_keyword("monitoring: Looks good."),
+            (Some(IncidentPhase::Monitoring), "Looks good.")
+        );
+    }
+
+    #[test]
+    fn keeps_text_without_keyword() {
+        for text in [
+            "https://example.com/incident is down.",
+            "Identified the cause: a full disk.",
+            "We are looking into it.",
+        ] {
+            assert_eq!(IncidentPhase::split_keyword(text), (None, text));
+        }
+    }
+}
@@ -1,4 +1,34 @@
+use chrono::prelude::*;
 use pulldown_cmark::{html, Options, Parser};
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+
+pub fn set_verbosity(quiet: bool, verbose: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+    VERBOSE.store(verbose, Ordering::Relaxed);
+}
+
+pub fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+pub fn is_verbose() -> bool {
+    VERBOSE.load(Ordering::Relaxed)
+}
+
+/// Prints progress information to stderr unless `--quiet` was given.
+macro_rules! progress {
+    ($($arg:tt)*) => {
+        if !$crate::utils::is_quiet() {
+            eprintln!($($arg)*);
+        }
+    };
+}
+pub(crate) use progress;
 
 pub fn render_markdown(text: String) -> String {
     let mut options = Options::empty();
@@ -8,3 +38,25 @@ pub fn render_markdown(text: String) -> String {
     html::push_html(&mut html_output, parser);
     html_output
 }
+
+pub fn parse_datetime_optional<'de, D>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(s) => Ok(Some(DateTime::from(
+            DateTime::parse_from_rfc3339(s).map_err(D::Error::custom)?,
+        ))),
+    }
+}
+
+pub fn parse_datetime<'de, D>(deserializer: D) -> Result<DateTime<Utc>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = Deserialize::deserialize(deserializer)?;
+    Ok(DateTime::from(
+        DateTime::parse_from_rfc3339(s).map_err(D::Error::custom)?,
+    ))
+}
@@ -0,0 +1,77 @@
+use signal_hook::consts::{SIGINT, SIGTERM};
+use std::fs::{metadata, read_dir};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::sleep;
+use std::time::{Duration, SystemTime};
+
+use crate::types::Error;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Detects changes to files by polling their modification times.
+pub struct Watcher {
+    paths: Vec<PathBuf>,
+    last_modified: Option<SystemTime>,
+}
+
+impl Watcher {
+    pub fn new(paths: Vec<PathBuf>) -> Self {
+        let last_modified = latest_modification(&paths);
+        Watcher {
+            paths,
+            last_modified,
+        }
+    }
+
+    /// Returns whether any of the watched files changed since the last call.
+    pub fn changed(&mut self) -> bool {
+        let last_modified = latest_modification(&self.paths);
+        if last_modified != self.last_modified {
+            self.last_modified = last_modified;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Catches SIGINT and SIGTERM, so watching can stop and clean up instead of
+/// the process being killed. A second signal exits right away.
+pub struct Signals(Arc<AtomicBool>);
+
+impl Signals {
+    pub fn register() -> Result<Self, Error> {
+        let stop = Arc::new(AtomicBool::new(false));
+        let error = |e| Error::Io(format!("Cannot handle signals: {}", e));
+        for signal in [SIGINT, SIGTERM] {
+            signal_hook::flag::register_conditional_shutdown(signal, 1, Arc::clone(&stop))
+                .map_err(error)?;
+            signal_hook::flag::register(signal, Arc::clone(&stop)).map_err(error)?;
+        }
+        Ok(Signals(stop))
+    }
+
+    /// Waits until the next poll. Returns `false` once a signal arrived.
+    pub fn wait(&self) -> bool {
+        sleep(POLL_INTERVAL);
+        !self.0.load(Ordering::Relaxed)
+    }
+}
+
+fn latest_modification(paths: &[PathBuf]) -> Option<SystemTime> {
+    paths.iter().filter_map(|path| modified(path)).max()
+}
+
+/// The most recent modification time of `path` or, for directories, of
+/// anything inside it. Removing a file changes the directory's time, too.
+fn modified(path: &Path) -> Option<SystemTime> {
+    let mut latest = metadata(path).ok()?.modified().ok();
+    if path.is_dir() {
+        for entry in read_dir(path).ok()?.flatten() {
+            latest = latest.max(modified(&entry.path()));
+        }
+    }
+    latest
+}
@@ -1,34 +1,69 @@
+use serde::Serialize;
 use std::fs::{copy, create_dir_all, read_dir, File};
-use std::io;
 use std::io::BufWriter;
 use std::path::{Path, PathBuf};
 use tera::{Context, Tera};
 
-pub fn render(tera: &Tera, context: &Context, template_file: &str, output_file: PathBuf) {
-    eprintln!("Writing file {} ...", output_file.to_str().unwrap());
-    let f = File::create(output_file).unwrap();
+use crate::types::Error;
+use crate::utils::progress;
+
+/// Templates shared by the built-in pages and feeds.
+pub const TEMPLATES: &[(&str, &str)] = &[("macros.html", include_str!("../templates/macros.html"))];
+
+pub fn render(
+    tera: &Tera,
+    context: &Context,
+    template_file: &str,
+    output_file: PathBuf,
+) -> Result<(), Error> {
+    progress!("Writing file {} ...", output_file.display());
+    let f = File::create(&output_file).map_err(|e| Error::io(&output_file, e))?;
     let b = BufWriter::new(f);
-    tera.render_to(template_file, context, b).unwrap();
-    eprintln!("    Done!");
+    tera.render_to(template_file, context, b)?;
+    progress!("    Done!");
+    Ok(())
+}
+
+/// Adds the built-in `templates` the template directory doesn't override, so
+/// template directories created by older versions keep working.
+pub fn add_default_templates(tera: &mut Tera, templates: &[(&str, &str)]) -> Result<(), Error> {
+    for (name, content) in templates.iter() {
+        if !tera.get_template_names().any(|existing| existing == *name) {
+            tera.add_raw_template(name, content)?;
+        }
+    }
+    Ok(())
 }
 
 // https://stackoverflow.com/a/65192210
-pub fn copy_dir(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> io::Result<()> {
-    eprintln!(
+pub fn copy_dir(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> Result<(), Error> {
+    let (src, dst) = (src.as_ref(), dst.as_ref());
+    progress!(
         "Copying directory {} to {} ...",
-        src.as_ref().to_str().unwrap(),
-        dst.as_ref().to_str().unwrap()
+        src.display(),
+        dst.display()
     );
-    create_dir_all(&dst)?;
-    for entry in read_dir(src)? {
-        let entry = entry?;
-        let ty = entry.file_type()?;
+    create_dir_all(dst).map_err(|e| Error::io(dst, e))?;
+    for entry in read_dir(src).map_err(|e| Error::io(src, e))? {
+        let entry = entry.map_err(|e| Error::io(src, e))?;
+        let ty = entry.file_type().map_err(|e| Error::io(&entry.path(), e))?;
         if ty.is_dir() {
-            copy_dir(entry.path(), dst.as_ref().join(entry.file_name()))?;
+            copy_dir(entry.path(), dst.join(entry.file_name()))?;
         } else {
-            eprintln!("--> {}", entry.path().to_str().unwrap());
-            copy(entry.path(), dst.as_ref().join(entry.file_name()))?;
+            progress!("--> {}", entry.path().display());
+            copy(entry.path(), dst.join(entry.file_name()))
+                .map_err(|e| Error::io(&entry.path(), e))?;
         }
     }
     Ok(())
 }
+
+/// Writes `value` as pretty printed JSON.
+pub fn write_json<T: Serialize>(value: &T, output_file: PathBuf) -> Result<(), Error> {
+    progress!("Writing file {} ...", output_file.display());
+    let f = File::create(&output_file).map_err(|e| Error::io(&output_file, e))?;
+    serde_json::to_writer_pretty(BufWriter::new(f), value)
+        .map_err(|e| Error::Io(format!("{}: {}", output_file.display(), e)))?;
+    progress!("    Done!");
+    Ok(())
+}